@@ -1,12 +1,14 @@
 use ethers::{
     abi::{ParamType, Token},
+    contract::{Multicall, MULTICALL_ADDRESS},
     prelude::abigen,
     providers::Middleware,
-    types::{Bytes, H160, U256},
+    types::{Bytes, H160, U256, U64},
 };
 use std::sync::Arc;
 
 use crate::{
+    abi,
     errors::CFMMError,
     pool::{Pool, UniswapV2Pool},
 };
@@ -55,8 +57,11 @@ pub async fn get_pairs_batch_request<M: Middleware>(
     Ok(pairs)
 }
 
+//Populates the pool data at `block_number`, or at the latest block if `None`.
+//Pools that are populated have their last synced block set to `block_number`.
 pub async fn get_pool_data_batch_request<M: Middleware>(
     pools: &mut [Pool],
+    block_number: Option<U64>,
     middleware: Arc<M>,
 ) -> Result<(), CFMMError<M>> {
     let mut target_addresses = vec![];
@@ -69,7 +74,11 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = deployer.call_raw().await?;
+    let return_data: Bytes = if let Some(block_number) = block_number {
+        deployer.block(block_number).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
     let return_data_tokens = ethers::abi::decode(
         &[ParamType::Array(Box::new(ParamType::Tuple(vec![
             ParamType::Address,   // token a
@@ -106,6 +115,10 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
                                 pool_data[5].to_owned().into_uint().unwrap().as_u128();
 
                             uniswap_v2_pool.fee = 300;
+
+                            if let Some(block_number) = block_number {
+                                uniswap_v2_pool.last_synced_block = block_number.as_u64();
+                            }
                         }
                     }
                     pool_idx += 1;
@@ -117,6 +130,69 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     Ok(())
 }
 
+//Syncs the reserves for each pool at `block_number`, or at the latest block if `None`, returning the addresses of the pools that could not be synced.
+//Only `getReserves` is called on each pool through Multicall3, so pools with tokens that do not implement the ERC20 metadata are still synced.
+//Pools that could not be synced (eg. self-destructed pools) are left unchanged, while synced pools have their last synced block set to `block_number`.
+pub async fn get_pool_reserves_batch_request<M: Middleware>(
+    pools: &mut [Pool],
+    block_number: Option<U64>,
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, CFMMError<M>> {
+    //Multicall3 is deployed at the same address on all supported chains, so the chain id is not requested
+    let mut multicall =
+        Multicall::new_with_chain_id(middleware.clone(), Some(MULTICALL_ADDRESS), None::<u64>)?;
+
+    if let Some(block_number) = block_number {
+        multicall = multicall.block(block_number);
+    }
+
+    for pool in pools.iter() {
+        multicall.add_call(
+            abi::IUniswapV2Pair::new(pool.address(), middleware.clone()).get_reserves(),
+            true,
+        );
+    }
+
+    let results = multicall.call_raw().await?;
+
+    Ok(populate_pool_reserves(pools, results, block_number))
+}
+
+fn populate_pool_reserves(
+    pools: &mut [Pool],
+    results: Vec<Result<Token, Bytes>>,
+    block_number: Option<U64>,
+) -> Vec<H160> {
+    let mut failed_pools = vec![];
+
+    for (pool, result) in pools.iter_mut().zip(results) {
+        let reserves = match result.map(|token| token.into_tuple()) {
+            Ok(Some(reserves)) => match reserves.as_slice() {
+                [Token::Uint(reserve_0), Token::Uint(reserve_1), ..] => {
+                    Some((reserve_0.as_u128(), reserve_1.as_u128()))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match (reserves, pool) {
+            (Some((reserve_0, reserve_1)), Pool::UniswapV2(uniswap_v2_pool)) => {
+                uniswap_v2_pool.reserve_0 = reserve_0;
+                uniswap_v2_pool.reserve_1 = reserve_1;
+
+                if let Some(block_number) = block_number {
+                    uniswap_v2_pool.last_synced_block = block_number.as_u64();
+                }
+            }
+
+            (_, pool) => failed_pools.push(pool.address()),
+        }
+    }
+
+    failed_pools
+}
+
 pub async fn get_v2_pool_data_batch_request<M: Middleware>(
     pool: &mut UniswapV2Pool,
     middleware: Arc<M>,
@@ -164,3 +240,56 @@ pub async fn get_v2_pool_data_batch_request<M: Middleware>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::types::H160;
+
+    use super::get_pool_reserves_batch_request;
+    use crate::{
+        mock_middleware::MockMiddleware,
+        pool::{Pool, UniswapV2Pool},
+    };
+
+    #[tokio::test]
+    async fn test_get_pool_reserves_batch_request_skips_failed_pools() {
+        let mut pools = vec![
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(1),
+                ..Default::default()
+            }),
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(2),
+                reserve_0: 10,
+                reserve_1: 20,
+                ..Default::default()
+            }),
+        ];
+
+        //Only the first pool returns its reserves
+        let middleware =
+            Arc::new(MockMiddleware::new(100).with_reserves(H160::from_low_u64_be(1), 5, 7));
+
+        let failed_pools =
+            get_pool_reserves_batch_request(&mut pools, Some(100.into()), middleware.clone())
+                .await
+                .unwrap();
+
+        assert_eq!(failed_pools, vec![H160::from_low_u64_be(2)]);
+        //Every pool is synced in a single multicall
+        assert_eq!(middleware.requests(), 1);
+
+        //Only the synced pool is marked as synced at the block
+        match (&pools[0], &pools[1]) {
+            (Pool::UniswapV2(synced_pool), Pool::UniswapV2(failed_pool)) => {
+                assert_eq!((synced_pool.reserve_0, synced_pool.reserve_1), (5, 7));
+                assert_eq!(synced_pool.last_synced_block, 100);
+                assert_eq!((failed_pool.reserve_0, failed_pool.reserve_1), (10, 20));
+                assert_eq!(failed_pool.last_synced_block, 0);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
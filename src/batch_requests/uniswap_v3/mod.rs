@@ -7,6 +7,8 @@ use ethers::{
     types::{Bytes, I256, U256, U64},
 };
 
+use uniswap_v3_math::tick_math::{MAX_TICK, MIN_TICK};
+
 use crate::{
     errors::CFMMError,
     pool::{Pool, UniswapV3Pool},
@@ -24,8 +26,11 @@ abigen!(
     "src/batch_requests/uniswap_v3/GetUniswapV3TickDataBatchRequest.json";
 );
 
+//Populates the pool data at `block_number`, or at the latest block if `None`.
+//Pools that are populated have their last synced block set to `block_number`.
 pub async fn get_pool_data_batch_request<M: Middleware>(
     pools: &mut [Pool],
+    block_number: Option<U64>,
     middleware: Arc<M>,
 ) -> Result<(), CFMMError<M>> {
     let mut target_addresses = vec![];
@@ -38,7 +43,11 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = deployer.call_raw().await?;
+    let return_data: Bytes = if let Some(block_number) = block_number {
+        deployer.block(block_number).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
 
     let return_data_tokens = ethers::abi::decode(
         &[ParamType::Array(Box::new(ParamType::Tuple(vec![
@@ -99,6 +108,10 @@ pub async fn get_pool_data_batch_request<M: Middleware>(
                             uniswap_v3_pool.liquidity_net =
                                 I256::from_raw(pool_data[9].to_owned().into_int().unwrap())
                                     .as_i128();
+
+                            if let Some(block_number) = block_number {
+                                uniswap_v3_pool.last_synced_block = block_number.as_u64();
+                            }
                         }
                     }
                     pool_idx += 1;
@@ -201,13 +214,24 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
     let deployer =
         GetUniswapV3TickDataBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = if block_number.is_some() {
-        deployer.block(block_number.unwrap()).call_raw().await?
+    let return_data: Bytes = if let Some(block_number) = block_number {
+        deployer.block(block_number).call_raw().await?
     } else {
         deployer.call_raw().await?
     };
 
-    let return_data_tokens = ethers::abi::decode(
+    Ok(parse_tick_data(&return_data, zero_for_one)?)
+}
+
+//Decodes the tick data and the block number returned by the tick data batch request.
+//The contract stops early once it reaches the min/max tick, leaving the remaining entries zeroed, so the tick data is
+//truncated after the first entry at the min/max tick.
+//When searching up for one for zero swaps, the contract sets ticks past the max tick to the min tick, so they are set back to the max tick.
+fn parse_tick_data(
+    return_data: &[u8],
+    zero_for_one: bool,
+) -> Result<(Vec<UniswapV3TickData>, U64), ethers::abi::Error> {
+    let mut return_data_tokens = ethers::abi::decode(
         &[
             ParamType::Array(Box::new(ParamType::Tuple(vec![
                 ParamType::Bool,
@@ -216,58 +240,51 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
             ]))),
             ParamType::Uint(32),
         ],
-        &return_data,
-    )?;
-
-    //TODO: handle these errors instead of using expect
-    let tick_data_array = return_data_tokens[0]
-        .to_owned()
-        .into_array()
-        .expect("Failed to convert initialized_ticks from Vec<Token> to Vec<i128>");
+        return_data,
+    )?
+    .into_iter();
+
+    let (tick_data_array, block_number) =
+        match (return_data_tokens.next(), return_data_tokens.next()) {
+            (Some(Token::Array(tick_data_array)), Some(Token::Uint(block_number))) => {
+                (tick_data_array, block_number)
+            }
+            _ => return Err(ethers::abi::Error::InvalidData),
+        };
 
     let mut tick_data = vec![];
 
     for tokens in tick_data_array {
-        if let Some(tick_data_tuple) = tokens.into_tuple() {
-            let initialized = tick_data_tuple[0]
-                .to_owned()
-                .into_bool()
-                .expect("Could not convert token to bool");
-
-            let initialized_tick = I256::from_raw(
-                tick_data_tuple[1]
-                    .to_owned()
-                    .into_int()
-                    .expect("Could not convert token to int"),
-            )
-            .as_i32();
-
-            let liquidity_net = I256::from_raw(
-                tick_data_tuple[2]
-                    .to_owned()
-                    .into_int()
-                    .expect("Could not convert token to int"),
-            )
-            .as_i128();
-
-            tick_data.push(UniswapV3TickData {
-                initialized,
-                tick: initialized_tick,
-                liquidity_net,
-            });
+        let (initialized, mut tick, liquidity_net) = match tokens.into_tuple().as_deref() {
+            Some([Token::Bool(initialized), Token::Int(tick), Token::Int(liquidity_net)]) => (
+                *initialized,
+                I256::from_raw(*tick).as_i32(),
+                I256::from_raw(*liquidity_net).as_i128(),
+            ),
+            _ => return Err(ethers::abi::Error::InvalidData),
+        };
+
+        if !zero_for_one && tick == MIN_TICK {
+            tick = MAX_TICK;
         }
-    }
 
-    let block_number = return_data_tokens[1]
-        .to_owned()
-        .into_uint()
-        .expect("Failed to convert block_number from Token to U64");
+        tick_data.push(UniswapV3TickData {
+            initialized,
+            tick,
+            liquidity_net,
+        });
+
+        if tick <= MIN_TICK || tick >= MAX_TICK {
+            break;
+        }
+    }
 
     Ok((tick_data, U64::from(block_number.as_u64())))
 }
 
 pub async fn sync_v3_pool_batch_request<M: Middleware>(
     pool: &mut UniswapV3Pool,
+    block_number: Option<U64>,
     middleware: Arc<M>,
 ) -> Result<(), CFMMError<M>> {
     let constructor_args = Token::Tuple(vec![Token::Address(pool.address())]);
@@ -275,7 +292,11 @@ pub async fn sync_v3_pool_batch_request<M: Middleware>(
     let deployer =
         SyncUniswapV3PoolBatchRequest::deploy(middleware.clone(), constructor_args).unwrap();
 
-    let return_data: Bytes = deployer.call_raw().await?;
+    let return_data: Bytes = if let Some(block_number) = block_number {
+        deployer.block(block_number).call_raw().await?
+    } else {
+        deployer.call_raw().await?
+    };
     let return_data_tokens = ethers::abi::decode(
         &[ParamType::Tuple(vec![
             ParamType::Uint(128), // liquidity
@@ -304,3 +325,60 @@ pub async fn sync_v3_pool_batch_request<M: Middleware>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::Token,
+        types::{I256, U256},
+    };
+    use uniswap_v3_math::tick_math::{MAX_TICK, MIN_TICK};
+
+    use super::parse_tick_data;
+
+    fn tick_data(initialized: bool, tick: i32, liquidity_net: i128) -> Token {
+        Token::Tuple(vec![
+            Token::Bool(initialized),
+            Token::Int(I256::from(tick).into_raw()),
+            Token::Int(I256::from(liquidity_net).into_raw()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_tick_data_truncates_at_max_tick() {
+        //The contract reached the max tick after two entries and set it to the min tick, leaving the last entry zeroed
+        let return_data = ethers::abi::encode(&[
+            Token::Array(vec![
+                tick_data(true, 887220, -1000),
+                tick_data(false, MIN_TICK, 0),
+                tick_data(false, 0, 0),
+            ]),
+            Token::Uint(U256::from(100)),
+        ]);
+
+        let (ticks, block_number) = parse_tick_data(&return_data, false).unwrap();
+
+        assert_eq!(block_number.as_u64(), 100);
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].liquidity_net, -1000);
+        assert_eq!(ticks.last().unwrap().tick, MAX_TICK);
+    }
+
+    #[test]
+    fn test_parse_tick_data_truncates_at_min_tick() {
+        let return_data = ethers::abi::encode(&[
+            Token::Array(vec![
+                tick_data(false, 0, 0),
+                tick_data(false, MIN_TICK, 0),
+                tick_data(false, 0, 0),
+            ]),
+            Token::Uint(U256::from(100)),
+        ]);
+
+        let (ticks, _) = parse_tick_data(&return_data, true).unwrap();
+
+        //The uninitialized tick at 0 is a word boundary, not a zeroed entry
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks.last().unwrap().tick, MIN_TICK);
+    }
+}
@@ -0,0 +1,117 @@
+//Blocking versions of the main entry points for use outside of an async runtime.
+//Each function spins up a current thread runtime for the duration of the call.
+use std::{future::Future, sync::Arc};
+
+use ethers::{
+    providers::Middleware,
+    types::{H160, U256},
+};
+
+use crate::{
+    checkpoint::{self, CheckpointSyncConfig, SyncedCheckpoint},
+    dex::{Dex, DexVariant},
+    errors::CFMMError,
+    pool::Pool,
+    quote::{self, Quote},
+    sync,
+};
+
+fn block_on<M: Middleware, T>(
+    future: impl Future<Output = Result<T, CFMMError<M>>>,
+) -> Result<T, CFMMError<M>> {
+    //Starting a runtime from within a runtime panics, so return an error instead
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err(CFMMError::BlockingInAsyncContext);
+    }
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future)
+}
+
+//Blocking version of `checkpoint::sync_pools_from_checkpoint`
+pub fn sync_pools_from_checkpoint<M: 'static + Middleware>(
+    path_to_checkpoint: &str,
+    step: usize,
+    middleware: Arc<M>,
+) -> Result<(Vec<Dex>, Vec<Pool>), CFMMError<M>> {
+    block_on(checkpoint::sync_pools_from_checkpoint(
+        path_to_checkpoint,
+        step,
+        middleware,
+    ))
+}
+
+//Blocking version of `checkpoint::sync_pools_from_checkpoint_with_config`
+pub fn sync_pools_from_checkpoint_with_config<M: 'static + Middleware>(
+    path_to_checkpoint: &str,
+    config: CheckpointSyncConfig,
+    middleware: Arc<M>,
+) -> Result<SyncedCheckpoint, CFMMError<M>> {
+    block_on(checkpoint::sync_pools_from_checkpoint_with_config(
+        path_to_checkpoint,
+        config,
+        middleware,
+    ))
+}
+
+//Blocking version of `sync::sync_pools_from_addresses`
+pub fn sync_pools_from_addresses<M: 'static + Middleware>(
+    addresses: Vec<(H160, DexVariant)>,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    block_on(sync::sync_pools_from_addresses(addresses, middleware))
+}
+
+//Blocking version of `quote::get_best_quote`
+pub fn get_best_quote<M: Middleware>(
+    token_in: H160,
+    token_out: H160,
+    amount_in: U256,
+    pools: &[Pool],
+    middleware: Arc<M>,
+) -> Result<Option<Quote>, CFMMError<M>> {
+    block_on(quote::get_best_quote(
+        token_in, token_out, amount_in, pools, middleware,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
+
+    use super::get_best_quote;
+    use crate::{errors::CFMMError, pool::Pool, test_utils::usdc_weth_pool};
+
+    #[test]
+    fn test_get_best_quote() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+        let pool = Pool::UniswapV2(usdc_weth_pool(0));
+
+        let quote = get_best_quote(
+            H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap(),
+            H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+            U256::from(1000000000),
+            &[pool],
+            middleware,
+        )
+        .unwrap();
+
+        assert!(quote.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_in_async_context() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let result = get_best_quote(H160::zero(), H160::zero(), U256::zero(), &[], middleware);
+
+        assert!(matches!(result, Err(CFMMError::BlockingInAsyncContext)));
+    }
+}
@@ -1,28 +1,100 @@
-use core::panic;
 use std::{
+    collections::HashSet,
     fs::read_to_string,
     panic::resume_unwind,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use ethers::{
     providers::Middleware,
-    types::{BlockNumber, H160, U256},
+    types::{BlockNumber, H160, U256, U64},
 };
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use futures::FutureExt;
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use tokio::task::JoinHandle;
+use tokio::{sync::Semaphore, task::JoinHandle};
 
 use crate::{
+    batch_requests,
     dex::{Dex, DexVariant},
-    errors::CFMMError,
+    errors::{CFMMError, CheckpointError, CheckpointLocation},
     pool::{Pool, UniswapV2Pool, UniswapV3Pool},
+    progress::{Progress, ProgressConfig, BLOCKS_SUFFIX, PAIRS_SUFFIX},
+    retry::{is_middleware_error, RetryPolicy},
     sync,
-    throttle::RequestThrottle,
+    throttle::{increment_or_sleep, RequestThrottle},
 };
 
+//Number of Uniswap V2 pools to sync per reserves batch request
+pub const DEFAULT_RESERVES_BATCH_SIZE: usize = 127;
+
+//Version of the checkpoint format written by `construct_checkpoint`
+pub const CHECKPOINT_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Checkpoint {
+    pub checkpoint_version: u64,
+    pub checkpoint_timestamp: u64,
+    pub block_number: u64,
+    pub dexes: Vec<Dex>,
+    pub pools: Vec<Pool>,
+}
+
+//Borrows the pools so that large pool sets are not cloned when writing a checkpoint
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    checkpoint_version: u64,
+    checkpoint_timestamp: u64,
+    block_number: u64,
+    dexes: Vec<Dex>,
+    pools: &'a [Pool],
+}
+
+//How the pools from the checkpoint are synced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointPoolSync {
+    //Uniswap V2 reserves are synced in batches of `reserves_batch_size` pools per call and Uniswap V3 pools with batch requests
+    Batched { reserves_batch_size: usize },
+    //Each pool is synced with `Pool::sync_pool_at_block`, running at most `max_concurrent_tasks` syncs at once
+    Concurrent { max_concurrent_tasks: usize },
+}
+
+//Dexes and synced pools from the checkpoint, with the addresses of the pools that could not be synced
+pub type SyncedCheckpoint = (Vec<Dex>, Vec<Pool>, Vec<H160>);
+
+//Options for `sync_pools_from_checkpoint_with_config`
+#[derive(Debug, Clone)]
+pub struct CheckpointSyncConfig {
+    //Number of blocks to get pair created logs for per request
+    pub step: usize,
+    //A limit of 0 disables the request throttle
+    pub requests_per_second_limit: usize,
+    pub pool_sync: CheckpointPoolSync,
+    pub progress_config: ProgressConfig,
+    //Shared by all of the sync tasks
+    pub retry_policy: RetryPolicy,
+}
+
+impl Default for CheckpointSyncConfig {
+    fn default() -> Self {
+        CheckpointSyncConfig {
+            step: 100000,
+            requests_per_second_limit: 0,
+            pool_sync: CheckpointPoolSync::Batched {
+                reserves_batch_size: DEFAULT_RESERVES_BATCH_SIZE,
+            },
+            progress_config: ProgressConfig::default(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
+
 //Get all pairs and sync reserve values for each Dex in the `dexes` vec.
 pub async fn sync_pools_from_checkpoint<M: 'static + Middleware>(
     path_to_checkpoint: &str,
@@ -39,65 +111,98 @@ pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
     requests_per_second_limit: usize,
     middleware: Arc<M>,
 ) -> Result<(Vec<Dex>, Vec<Pool>), CFMMError<M>> {
-    let current_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
+    let config = CheckpointSyncConfig {
+        step,
+        requests_per_second_limit,
+        ..Default::default()
+    };
 
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    let (dexes, pools, _) =
+        sync_pools_from_checkpoint_with_config(path_to_checkpoint, config, middleware).await?;
 
-    //Read in checkpoint
-    let (dexes, pools, checkpoint_block_number) = deconstruct_checkpoint(path_to_checkpoint);
+    Ok((dexes, pools))
+}
 
-    //Sort all of the pools from the checkpoint into uniswapv2 and uniswapv3 pools so we can sync them concurrently
-    let (uinswap_v2_pools, uniswap_v3_pools) = sort_pool_variants(pools);
+//Syncs the pools from the checkpoint, gets all pools created since each Dex was last synced and updates the checkpoint with the merged pool set.
+//Also returns the addresses of the checkpoint pools whose reserves could not be synced with `CheckpointPoolSync::Batched`.
+//With `CheckpointPoolSync::Concurrent`, pools that fail to sync keep their previous state and are not returned.
+pub async fn sync_pools_from_checkpoint_with_config<M: 'static + Middleware>(
+    path_to_checkpoint: &str,
+    config: CheckpointSyncConfig,
+    middleware: Arc<M>,
+) -> Result<SyncedCheckpoint, CFMMError<M>> {
+    let current_block = config
+        .retry_policy
+        .retry(|| {
+            let middleware = middleware.clone();
+            async move {
+                middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            }
+        })
+        .await?;
 
-    let mut aggregated_pools = vec![];
-    let mut handles = vec![];
+    let request_throttle = Arc::new(Mutex::new(
+        RequestThrottle::new(config.requests_per_second_limit)
+            .with_retry_policy(config.retry_policy),
+    ));
+    //Initialize the progress bars
+    let progress = Progress::new(config.progress_config);
 
-    //Sync all uniswap v2 pools from checkpoint
-    if !uinswap_v2_pools.is_empty() {
-        handles.push(
-            batch_sync_pools_from_checkpoint(
-                uinswap_v2_pools,
-                DexVariant::UniswapV2,
-                multi_progress_bar.add(ProgressBar::new(0)),
-                request_throttle.clone(),
-                middleware.clone(),
+    //Read in checkpoint
+    let (dexes, pools, _) = deconstruct_checkpoint(path_to_checkpoint)?;
+
+    let handles = get_new_pools_since_last_sync(
+        &dexes,
+        current_block,
+        config.step,
+        request_throttle.clone(),
+        progress.clone(),
+        middleware.clone(),
+    )
+    .await?;
+
+    //Pools from the checkpoint are aggregated first so that they take precedence when deduping
+    let (mut aggregated_pools, failed_pools) = match config.pool_sync {
+        CheckpointPoolSync::Batched {
+            reserves_batch_size,
+        } => {
+            batch_sync_checkpoint_pools(
+                pools,
+                reserves_batch_size,
+                current_block,
+                progress.clone(),
+                request_throttle,
+                middleware,
             )
-            .await,
-        );
-    }
+            .await?
+        }
 
-    //Sync all uniswap v3 pools from checkpoint
-    if !uniswap_v3_pools.is_empty() {
-        handles.push(
-            batch_sync_pools_from_checkpoint(
-                uniswap_v3_pools,
-                DexVariant::UniswapV3,
-                multi_progress_bar.add(ProgressBar::new(0)),
-                request_throttle.clone(),
-                middleware.clone(),
+        CheckpointPoolSync::Concurrent {
+            max_concurrent_tasks,
+        } => {
+            let progress_bar = progress.add_bar();
+            progress.start_stage(
+                &progress_bar,
+                "Syncing all pools from checkpoint".to_string(),
+            );
+            progress_bar.inc_length(pools.len() as u64);
+
+            let pools = sync_pools_concurrently(
+                pools,
+                current_block,
+                max_concurrent_tasks,
+                request_throttle,
+                progress_bar,
+                middleware,
             )
-            .await,
-        );
-    }
+            .await?;
 
-    //Sync all pools from the since synced block
-    handles.extend(
-        get_new_pools_from_range(
-            dexes.clone(),
-            checkpoint_block_number,
-            current_block.into(),
-            step,
-            request_throttle,
-            multi_progress_bar,
-            middleware.clone(),
-        )
-        .await,
-    );
+            (pools, vec![])
+        }
+    };
 
     for handle in handles {
         match handle.await {
@@ -113,55 +218,283 @@ pub async fn sync_pools_from_checkpoint_with_throttle<M: 'static + Middleware>(
         }
     }
 
+    progress.finish();
+
+    //The range searched for new pools overlaps the range that the checkpoint was synced to, so remove any duplicates
+    let aggregated_pools = dedup_pools_by_address(aggregated_pools);
+
     //update the sync checkpoint
     construct_checkpoint(
         dexes.clone(),
         &aggregated_pools,
         current_block.as_u64(),
         path_to_checkpoint,
-    );
+    )?;
+
+    Ok((dexes, aggregated_pools, failed_pools))
+}
+
+//Syncs the Uniswap V2 and Uniswap V3 pools from the checkpoint concurrently with batch requests,
+//returning the synced pools and the addresses of the pools whose reserves could not be synced
+async fn batch_sync_checkpoint_pools<M: 'static + Middleware>(
+    pools: Vec<Pool>,
+    reserves_batch_size: usize,
+    block_number: U64,
+    progress: Progress,
+    request_throttle: Arc<Mutex<RequestThrottle>>,
+    middleware: Arc<M>,
+) -> Result<(Vec<Pool>, Vec<H160>), CFMMError<M>> {
+    //Sort all of the pools from the checkpoint into uniswapv2 and uniswapv3 pools so we can sync them concurrently
+    let (uinswap_v2_pools, uniswap_v3_pools) = sort_pool_variants(pools);
+
+    let mut checkpoint_handles = vec![];
+
+    //Sync all pools from checkpoint
+    for (pools, dex_variant) in [
+        (uinswap_v2_pools, DexVariant::UniswapV2),
+        (uniswap_v3_pools, DexVariant::UniswapV3),
+    ] {
+        if !pools.is_empty() {
+            checkpoint_handles.push(
+                batch_sync_pools_from_checkpoint(
+                    pools,
+                    dex_variant,
+                    reserves_batch_size,
+                    block_number,
+                    progress.clone(),
+                    request_throttle.clone(),
+                    middleware.clone(),
+                )
+                .await,
+            );
+        }
+    }
+
+    let mut synced_pools = vec![];
+    let mut failed_pools = vec![];
+
+    for handle in checkpoint_handles {
+        match handle.await {
+            Ok(sync_result) => {
+                let (pools, failed) = sync_result?;
+                synced_pools.extend(pools);
+                failed_pools.extend(failed);
+            }
+            Err(err) => {
+                {
+                    if err.is_panic() {
+                        // Resume the panic on the main task
+                        resume_unwind(err.into_panic());
+                    }
+                }
+            }
+        }
+    }
 
-    Ok((dexes, aggregated_pools))
+    Ok((synced_pools, failed_pools))
 }
 
+//Syncs each pool at `block_number` with `Pool::sync_pool_at_block`, running at most `max_concurrent_tasks` syncs at once.
+//Pools are returned in the same order that they were passed in. Pools that fail to sync because of a contract error keep their previous state,
+//while a middleware error stops any further syncs and is returned. A sync task that is cancelled is returned as `CFMMError::JoinError`.
+pub async fn sync_pools_concurrently<M: 'static + Middleware>(
+    pools: Vec<Pool>,
+    block_number: U64,
+    max_concurrent_tasks: usize,
+    request_throttle: Arc<Mutex<RequestThrottle>>,
+    progress_bar: ProgressBar,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    let semaphore = Arc::new(Semaphore::new(max_concurrent_tasks.max(1)));
+    let aborted = Arc::new(AtomicBool::new(false));
+    let retry_policy = request_throttle
+        .lock()
+        .expect("Error when acquiring request throttle mutex lock")
+        .retry_policy();
+    let mut handles = Vec::with_capacity(pools.len());
+
+    for mut pool in pools {
+        //Wait for a task to finish before spawning the next one
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore should never be closed");
+
+        if aborted.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let request_throttle = request_throttle.clone();
+        let progress_bar = progress_bar.clone();
+        let aborted = aborted.clone();
+        let retry_policy = retry_policy.clone();
+        let middleware = middleware.clone();
+
+        handles.push(tokio::spawn(async move {
+            let _permit = permit;
+
+            let sync_result = retry_policy
+                .retry_with(&mut pool, |pool| {
+                    let request_throttle = request_throttle.clone();
+                    let middleware = middleware.clone();
+                    async move {
+                        increment_or_sleep(&request_throttle, 1).await;
+                        pool.sync_pool_at_block(block_number, middleware).await
+                    }
+                    .boxed()
+                })
+                .await;
+            progress_bar.inc(1);
+
+            match sync_result {
+                Ok(_) => Ok(pool),
+                Err(err) if is_middleware_error(&err) => {
+                    aborted.store(true, Ordering::SeqCst);
+                    Err(err)
+                }
+                //Contract errors only affect this pool, so keep the pool with its previous state
+                Err(_) => Ok(pool),
+            }
+        }));
+    }
+
+    let mut synced_pools = Vec::with_capacity(handles.len());
+
+    for handle in handles {
+        match handle.await {
+            Ok(sync_result) => synced_pools.push(sync_result?),
+            Err(err) => {
+                if err.is_panic() {
+                    // Resume the panic on the main task
+                    resume_unwind(err.into_panic());
+                }
+
+                //The task was cancelled, so the pool it was syncing can not be returned
+                return Err(CFMMError::JoinError(err));
+            }
+        }
+    }
+
+    Ok(synced_pools)
+}
+
+//Removes pools with duplicate addresses, keeping the first occurrence of each address
+pub fn dedup_pools_by_address(pools: Vec<Pool>) -> Vec<Pool> {
+    let mut seen_addresses = HashSet::new();
+
+    pools
+        .into_iter()
+        .filter(|pool| seen_addresses.insert(pool.address()))
+        .collect()
+}
+
+//Syncs the pools from a checkpoint, returning the synced pools and the addresses of the pools that could not be synced.
+//Pools are synced at `block_number`. Uniswap V2 pools already have their token data from the checkpoint so only the reserves are synced.
 pub async fn batch_sync_pools_from_checkpoint<M: 'static + Middleware>(
     mut pools: Vec<Pool>,
     dex_variant: DexVariant,
-    progress_bar: ProgressBar,
+    reserves_batch_size: usize,
+    block_number: U64,
+    progress: Progress,
     request_throttle: Arc<Mutex<RequestThrottle>>,
     middleware: Arc<M>,
-) -> JoinHandle<Result<Vec<Pool>, CFMMError<M>>> {
+) -> JoinHandle<Result<(Vec<Pool>, Vec<H160>), CFMMError<M>>> {
     let dex = Dex::new(H160::zero(), dex_variant, 0, None);
+    let progress_bar = progress.add_bar();
 
     //Spawn a new thread to get all pools and sync data for each dex
     tokio::spawn(async move {
-        progress_bar.set_style(
-            ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                .expect("Error when setting progress bar style")
-                .progress_chars("##-"),
-        );
-        progress_bar.set_length(pools.len() as u64);
-
-        match dex {
+        let failed_pools = match dex {
             Dex::UniswapV2(_) => {
-                progress_bar.set_message("Syncing all Uniswap V2 pool variants from checkpoint");
+                progress.start_stage(
+                    &progress_bar,
+                    "Syncing all Uniswap V2 pool variants from checkpoint".to_string(),
+                );
+                progress_bar.inc_length(pools.len() as u64);
+
+                //Get all pool reserves via batched calls
+                batch_sync_pools(
+                    &mut pools,
+                    reserves_batch_size,
+                    block_number,
+                    request_throttle,
+                    progress_bar,
+                    middleware,
+                )
+                .await?
             }
+
             Dex::UniswapV3(_) => {
-                progress_bar.set_message("Syncing all Uniswap V3 pool variants from checkpoint");
-            }
-        }
+                progress.start_stage(
+                    &progress_bar,
+                    "Syncing all Uniswap V3 pool variants from checkpoint".to_string(),
+                );
+                progress_bar.inc_length(pools.len() as u64);
+
+                //Get all pool data via batched calls
+                dex.get_all_pool_data_at_block(
+                    &mut pools,
+                    Some(block_number),
+                    request_throttle,
+                    progress_bar,
+                    middleware,
+                )
+                .await?;
 
-        //Get all pool data via batched calls
-        dex.get_all_pool_data(&mut pools, request_throttle, progress_bar, middleware)
-            .await?;
+                vec![]
+            }
+        };
 
         //Clean empty pools
         pools = sync::remove_empty_pools(pools);
 
-        Ok::<_, CFMMError<M>>(pools)
+        Ok::<_, CFMMError<M>>((pools, failed_pools))
     })
 }
 
+//Syncs the reserves of Uniswap V2 pools at `block_number` in batches of `batch_size` pools, incrementing the request throttle once per batch.
+//Returns the addresses of the pools whose reserves could not be synced, these pools are left unchanged.
+pub async fn batch_sync_pools<M: 'static + Middleware>(
+    pools: &mut [Pool],
+    batch_size: usize,
+    block_number: U64,
+    request_throttle: Arc<Mutex<RequestThrottle>>,
+    progress_bar: ProgressBar,
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, CFMMError<M>> {
+    let mut failed_pools = vec![];
+    let retry_policy = request_throttle
+        .lock()
+        .expect("Error when acquiring request throttle mutex lock")
+        .retry_policy();
+
+    for pools in pools.chunks_mut(batch_size.max(1)) {
+        failed_pools.extend(
+            retry_policy
+                .retry_with(pools, |pools| {
+                    let request_throttle = request_throttle.clone();
+                    let middleware = middleware.clone();
+                    async move {
+                        increment_or_sleep(&request_throttle, 1).await;
+                        batch_requests::uniswap_v2::get_pool_reserves_batch_request(
+                            pools,
+                            Some(block_number),
+                            middleware,
+                        )
+                        .await
+                    }
+                    .boxed()
+                })
+                .await?,
+        );
+
+        progress_bar.inc(pools.len() as u64);
+    }
+
+    Ok(failed_pools)
+}
+
 pub fn sort_pool_variants(pools: Vec<Pool>) -> (Vec<Pool>, Vec<Pool>) {
     let mut uniswap_v2_pools = vec![];
     let mut uniswap_v3_pools = vec![];
@@ -176,13 +509,56 @@ pub fn sort_pool_variants(pools: Vec<Pool>) -> (Vec<Pool>, Vec<Pool>) {
     (uniswap_v2_pools, uniswap_v3_pools)
 }
 
+//Gets the pools created since each dex was last synced. The block stored for each dex in the checkpoint is its latest synced block.
+//Dexes that are already synced to `current_block` are skipped.
+pub async fn get_new_pools_since_last_sync<M: 'static + Middleware>(
+    dexes: &[Dex],
+    current_block: U64,
+    step: usize,
+    request_throttle: Arc<Mutex<RequestThrottle>>,
+    progress: Progress,
+    middleware: Arc<M>,
+) -> Result<Vec<JoinHandle<Result<Vec<Pool>, CFMMError<M>>>>, CheckpointError> {
+    let mut handles = vec![];
+    for (dex_index, dex) in dexes.iter().enumerate() {
+        //The block is always a number for dexes read from a checkpoint, but dexes can be created with a block tag
+        let latest_synced_block =
+            dex.creation_block()
+                .as_number()
+                .ok_or(CheckpointError::InvalidField {
+                    location: CheckpointLocation::Dex(dex_index),
+                    field: "block_number",
+                })?;
+
+        //Nothing to fetch if the dex is already synced to the current block
+        if latest_synced_block >= current_block {
+            continue;
+        }
+
+        handles.extend(
+            get_new_pools_from_range(
+                vec![*dex],
+                dex.creation_block(),
+                current_block.into(),
+                step,
+                request_throttle.clone(),
+                progress.clone(),
+                middleware.clone(),
+            )
+            .await,
+        );
+    }
+
+    Ok(handles)
+}
+
 pub async fn get_new_pools_from_range<M: 'static + Middleware>(
     dexes: Vec<Dex>,
     from_block: BlockNumber,
     to_block: BlockNumber,
     step: usize,
     request_throttle: Arc<Mutex<RequestThrottle>>,
-    multi_progress_bar: MultiProgress,
+    progress: Progress,
     middleware: Arc<M>,
 ) -> Vec<JoinHandle<Result<Vec<Pool>, CFMMError<M>>>> {
     //Create the filter with all the pair created events
@@ -192,21 +568,16 @@ pub async fn get_new_pools_from_range<M: 'static + Middleware>(
     for dex in dexes {
         let middleware = middleware.clone();
         let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+        let progress = progress.clone();
+        let progress_bar = progress.add_bar();
 
         //Spawn a new thread to get all pools and sync data for each dex
         handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pools from the dex
-            progress_bar.set_message(format!(
-                "Getting new all pools from: {}",
-                dex.factory_address()
-            ));
+            progress.start_stage(
+                &progress_bar,
+                format!("Getting new all pools from: {}", dex.factory_address()),
+            );
 
             let mut pools = dex
                 .get_all_pools_from_logs_within_range(
@@ -219,23 +590,19 @@ pub async fn get_new_pools_from_range<M: 'static + Middleware>(
                 )
                 .await?;
 
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pool data and sync the pool
-            progress_bar.set_message(format!(
-                "Getting all pool data for new pools from: {}",
-                dex.factory_address()
-            ));
-
-            progress_bar.set_length(pools.len() as u64);
+            progress.start_stage(
+                &progress_bar,
+                format!(
+                    "Getting all pool data for new pools from: {}",
+                    dex.factory_address()
+                ),
+            );
+            progress_bar.inc_length(pools.len() as u64);
 
-            dex.get_all_pool_data(
+            dex.get_all_pool_data_at_block(
                 &mut pools,
+                to_block.as_number(),
                 request_throttle.clone(),
                 progress_bar.clone(),
                 middleware.clone(),
@@ -269,28 +636,67 @@ pub async fn generate_checkpoint_with_throttle<M: 'static + Middleware>(
     step: usize,
     requests_per_second_limit: usize,
     checkpoint_file_name: &str,
+) -> Result<(), CFMMError<M>> {
+    generate_checkpoint_with_progress(
+        dexes,
+        middleware,
+        step,
+        requests_per_second_limit,
+        checkpoint_file_name,
+        ProgressConfig::default(),
+        RetryPolicy::default(),
+    )
+    .await
+}
+
+//Get all pairs and sync reserve values for each Dex in the `dexes` vec, rendering progress according to `progress_config`.
+//Rate limited requests are retried according to `retry_policy`, which is shared by the tasks syncing each Dex.
+pub async fn generate_checkpoint_with_progress<M: 'static + Middleware>(
+    dexes: Vec<Dex>,
+    middleware: Arc<M>,
+    step: usize,
+    requests_per_second_limit: usize,
+    checkpoint_file_name: &str,
+    progress_config: ProgressConfig,
+    retry_policy: RetryPolicy,
 ) -> Result<(), CFMMError<M>> {
     //Initialize a new request throttle
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
+    let request_throttle = Arc::new(Mutex::new(
+        RequestThrottle::new(requests_per_second_limit).with_retry_policy(retry_policy.clone()),
+    ));
+
+    let latest_block = retry_policy
+        .retry(|| {
+            let (middleware, request_throttle) = (middleware.clone(), &request_throttle);
+            async move {
+                increment_or_sleep(request_throttle, 1).await;
+                middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            }
+        })
+        .await?;
 
     //Aggregate the populated pools from each thread
     let mut aggregated_pools: Vec<Pool> = vec![];
     let mut handles = vec![];
 
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    //Initialize the progress bars
+    let progress = Progress::new(progress_config);
 
     //For each dex supplied, get all pair created events and get reserve values
     for dex in dexes.clone() {
         let async_provider = middleware.clone();
         let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+        let progress = progress.clone();
+        let progress_bar = progress.add_bar();
 
         handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7} Blocks")
-                    .unwrap()
-                    .progress_chars("##-"),
+            progress.start_stage_with_suffix(
+                &progress_bar,
+                format!("Getting all pools from: {}", dex.factory_address()),
+                BLOCKS_SUFFIX,
             );
 
             let mut pools = dex
@@ -302,28 +708,26 @@ pub async fn generate_checkpoint_with_throttle<M: 'static + Middleware>(
                 )
                 .await?;
 
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7} Pairs")
-                    .unwrap()
-                    .progress_chars("##-"),
+            progress.start_stage_with_suffix(
+                &progress_bar,
+                format!("Getting all pool data for: {}", dex.factory_address()),
+                PAIRS_SUFFIX,
             );
+            progress_bar.inc_length(pools.len() as u64);
 
-            dex.get_all_pool_data(
+            dex.get_all_pool_data_at_block(
                 &mut pools,
+                Some(latest_block),
                 request_throttle.clone(),
                 progress_bar.clone(),
                 async_provider.clone(),
             )
             .await?;
 
-            progress_bar.finish_and_clear();
-            progress_bar.set_message(format!(
-                "Finished syncing pools for {} ✅",
-                dex.factory_address()
-            ));
-
-            progress_bar.finish();
+            progress.finish_stage(
+                &progress_bar,
+                format!("Finished syncing pools for {} ✅", dex.factory_address()),
+            );
 
             Ok::<_, CFMMError<M>>(pools)
         }));
@@ -346,364 +750,820 @@ pub async fn generate_checkpoint_with_throttle<M: 'static + Middleware>(
     //Clean empty pools
     aggregated_pools = sync::remove_empty_pools(aggregated_pools);
 
-    let latest_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
-
-    println!("total pools :{}", aggregated_pools.len());
+    progress.finish();
+    progress.println(format!("total pools :{}", aggregated_pools.len()));
 
     construct_checkpoint(
         dexes,
         &aggregated_pools,
         latest_block.as_u64(),
         checkpoint_file_name,
-    );
+    )?;
 
     Ok(())
 }
 
-pub fn deconstruct_checkpoint(checkpoint_path: &str) -> (Vec<Dex>, Vec<Pool>, BlockNumber) {
-    let mut dexes = vec![];
+//Reads the dexes and pools from a checkpoint without making any RPC calls.
+//Pools from checkpoints written before `checkpoint_version` was added are loaded without their reserves or price state.
+pub fn load_pools_from_checkpoint(
+    checkpoint_path: &str,
+) -> Result<(Vec<Dex>, Vec<Pool>), CheckpointError> {
+    let (dexes, pools, _) = deconstruct_checkpoint(checkpoint_path)?;
 
-    let checkpoint_json: serde_json::Value = serde_json::from_str(
-        read_to_string(checkpoint_path)
-            .expect("Error when reading in checkpoint json")
-            .as_str(),
-    )
-    .expect("Error when converting checkpoint file contents to serde_json::Value");
+    Ok((dexes, pools))
+}
 
-    let block_number = checkpoint_json
-        .get("block_number")
-        .expect("Could not get block_number from checkpoint")
-        .as_u64()
-        .expect("Could not convert block_number to u64");
+pub fn deconstruct_checkpoint(
+    checkpoint_path: &str,
+) -> Result<(Vec<Dex>, Vec<Pool>, BlockNumber), CheckpointError> {
+    let checkpoint_contents = read_to_string(checkpoint_path)?;
+    let checkpoint_json: Value = serde_json::from_str(checkpoint_contents.as_str())?;
+
+    let checkpoint_map = checkpoint_json
+        .as_object()
+        .ok_or(CheckpointError::InvalidField {
+            location: CheckpointLocation::Root,
+            field: "checkpoint",
+        })?;
+
+    //Checkpoints without a version were written before pool state was persisted
+    if !checkpoint_map.contains_key("checkpoint_version") {
+        return deconstruct_legacy_checkpoint(checkpoint_map);
+    }
 
-    for dex_data in checkpoint_json
-        .get("dexes")
-        .expect("Could not get checkpoint_data")
-        .as_array()
-        .expect("Could not unwrap checkpoint json into array")
+    let checkpoint_version = get_u64(
+        checkpoint_map,
+        "checkpoint_version",
+        CheckpointLocation::Root,
+    )?;
+
+    if checkpoint_version != CHECKPOINT_VERSION {
+        return Err(CheckpointError::UnsupportedVersion(checkpoint_version));
+    }
+
+    //Deserialize from the file contents rather than the `Value` so that u128 reserves and liquidity are not truncated
+    let checkpoint: Checkpoint = serde_json::from_str(checkpoint_contents.as_str())?;
+
+    Ok((
+        checkpoint.dexes,
+        checkpoint.pools,
+        BlockNumber::Number(checkpoint.block_number.into()),
+    ))
+}
+
+//Parses checkpoints written before `checkpoint_version` was added, which only store the pool metadata
+pub fn deconstruct_legacy_checkpoint(
+    checkpoint_map: &Map<String, Value>,
+) -> Result<(Vec<Dex>, Vec<Pool>, BlockNumber), CheckpointError> {
+    let mut dexes = vec![];
+
+    let block_number = get_u64(checkpoint_map, "block_number", CheckpointLocation::Root)?;
+
+    for (dex_index, dex_data) in get_array(checkpoint_map, "dexes", CheckpointLocation::Root)?
         .iter()
+        .enumerate()
     {
-        let dex = deconstruct_dex_from_checkpoint(
-            dex_data
-                .as_object()
-                .expect("Dex checkpoint is not formatted correctly"),
-        );
+        let dex_map = dex_data.as_object().ok_or(CheckpointError::InvalidField {
+            location: CheckpointLocation::Dex(dex_index),
+            field: "dex",
+        })?;
 
-        dexes.push(dex);
+        dexes.push(deconstruct_dex_from_checkpoint(dex_map, dex_index)?);
     }
 
     //get all pools
-    let pools_array = checkpoint_json
-        .get("pools")
-        .expect("Could not get pools from checkpoint")
-        .as_array()
-        .expect("Could not convert pools to value array");
+    let pools_array = get_array(checkpoint_map, "pools", CheckpointLocation::Root)?;
 
-    let pools = deconstruct_pools_from_checkpoint(pools_array);
+    let pools = deconstruct_pools_from_checkpoint(pools_array)?;
 
-    (dexes, pools, BlockNumber::Number(block_number.into()))
+    Ok((dexes, pools, BlockNumber::Number(block_number.into())))
 }
 
-pub fn deconstruct_dex_from_checkpoint(dex_map: &Map<String, Value>) -> Dex {
-    let dex_variant = match dex_map
-        .get("dex_variant")
-        .expect("Checkpoint formatted incorrectly, could not get dex_variant.")
-        .as_str()
-        .expect("Could not convert dex variant to string")
-        .to_lowercase()
-        .as_str()
-    {
-        "uniswapv2" => DexVariant::UniswapV2,
-        "uniswapv3" => DexVariant::UniswapV3,
-        other => {
-            panic!("Unrecognized dex variant in checkpoint: {:?}", other)
+pub fn deconstruct_dex_from_checkpoint(
+    dex_map: &Map<String, Value>,
+    dex_index: usize,
+) -> Result<Dex, CheckpointError> {
+    let location = CheckpointLocation::Dex(dex_index);
+
+    let dex_variant = get_dex_variant(dex_map, location)?;
+    let block_number = get_u64(dex_map, "block_number", location)?;
+    let factory_address = get_address(dex_map, "factory_address", location)?;
+
+    //Older checkpoints store the fee as a string
+    let fee = match dex_map.get("fee") {
+        Some(Value::String(fee)) => {
+            Some(
+                fee.parse::<u64>()
+                    .map_err(|_| CheckpointError::InvalidField {
+                        location,
+                        field: "fee",
+                    })?,
+            )
         }
+        Some(_) => Some(get_u64(dex_map, "fee", location)?),
+        None => None,
     };
 
-    let block_number = dex_map
-        .get("block_number")
-        .expect("Checkpoint formatted incorrectly, could not get dex latest_synced_block.")
-        .as_u64()
-        .expect("Could not convert latest_synced_block to u64");
-
-    let factory_address = H160::from_str(
-        dex_map
-            .get("factory_address")
-            .expect("Checkpoint formatted incorrectly, could not get dex factory_address.")
-            .as_str()
-            .expect("Could not convert factory_address to str"),
-    )
-    .expect("Could not convert checkpoint factory_address to H160.");
-
-    let fee = dex_map
-        .get("fee")
-        .map(|fee| fee.as_u64().expect("Could not convert fee to u64"));
-
-    Dex::new(factory_address, dex_variant, block_number, fee)
+    Ok(Dex::new(factory_address, dex_variant, block_number, fee))
 }
 
-pub fn deconstruct_pools_from_checkpoint(pools_array: &Vec<Value>) -> Vec<Pool> {
+pub fn deconstruct_pools_from_checkpoint(
+    pools_array: &[Value],
+) -> Result<Vec<Pool>, CheckpointError> {
     let mut pools = vec![];
 
-    for pool_value in pools_array {
+    for (pool_index, pool_value) in pools_array.iter().enumerate() {
+        let location = CheckpointLocation::Pool(pool_index);
+
         let pool_map = pool_value
             .as_object()
-            .expect("Could not convert pool value to map");
-
-        let pool_dex_variant = match pool_map
-            .get("dex_variant")
-            .expect("Could not get pool dex_variant")
-            .as_str()
-            .expect("Could not convert dex_variant to str")
-            .to_lowercase()
-            .as_str()
-        {
-            "uniswapv2" => DexVariant::UniswapV2,
-            "uniswapv3" => DexVariant::UniswapV3,
-            _ => {
-                panic!("Unrecognized pool dex variant")
-            }
-        };
+            .ok_or(CheckpointError::InvalidField {
+                location,
+                field: "pool",
+            })?;
+
+        let pool_dex_variant = get_dex_variant(pool_map, location)?;
+        let addr = get_address(pool_map, "address", location)?;
+        let token_a = get_address(pool_map, "token_a", location)?;
+        let token_a_decimals: u8 = get_uint(pool_map, "token_a_decimals", location)?;
+        let token_b = get_address(pool_map, "token_b", location)?;
+        let token_b_decimals: u8 = get_uint(pool_map, "token_b_decimals", location)?;
+        let fee: u32 = get_uint(pool_map, "fee", location)?;
 
         match pool_dex_variant {
-            DexVariant::UniswapV2 | DexVariant::UniswapV3 => {
-                let addr = H160::from_str(
-                    pool_map
-                        .get("address")
-                        .unwrap_or_else(|| panic!("Could not get pool address {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert pool address to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_a to H160");
-
-                let token_a = H160::from_str(
-                    pool_map
-                        .get("token_a")
-                        .unwrap_or_else(|| panic!("Could not get token_a {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert token_a to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_a to H160");
-
-                let token_a_decimals = pool_map
-                    .get("token_a_decimals")
-                    .unwrap_or_else(|| panic!("Could not get token_a_decimals {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert token_a_decimals to u64")
-                    as u8;
-
-                let token_b = H160::from_str(
-                    pool_map
-                        .get("token_b")
-                        .unwrap_or_else(|| panic!("Could not get token_b {:?}", pool_map))
-                        .as_str()
-                        .unwrap_or_else(|| {
-                            panic!("Could not convert token_b to str {:?}", pool_map)
-                        }),
-                )
-                .expect("Could not convert token_b to H160");
-
-                let token_b_decimals = pool_map
-                    .get("token_b_decimals")
-                    .unwrap_or_else(|| panic!("Could not get token_b_decimals {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert token_b_decimals to u64")
-                    as u8;
-
-                let fee = pool_map
-                    .get("fee")
-                    .unwrap_or_else(|| panic!("Could not get fee {:?}", pool_map))
-                    .as_u64()
-                    .expect("Could not convert fee to u64") as u32;
-
-                match pool_dex_variant {
-                    DexVariant::UniswapV2 => {
-                        pools.push(Pool::UniswapV2(UniswapV2Pool::new(
-                            addr,
-                            token_a,
-                            token_a_decimals,
-                            token_b,
-                            token_b_decimals,
-                            0,
-                            0,
-                            fee,
-                        )));
-                    }
+            DexVariant::UniswapV2 => {
+                pools.push(Pool::UniswapV2(UniswapV2Pool::new(
+                    addr,
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    0,
+                    0,
+                    fee,
+                )));
+            }
 
-                    DexVariant::UniswapV3 => {
-                        pools.push(Pool::UniswapV3(UniswapV3Pool::new(
-                            addr,
-                            token_a,
-                            token_a_decimals,
-                            token_b,
-                            token_b_decimals,
-                            fee,
-                            0,
-                            U256::zero(),
-                            0,
-                            0,
-                            0,
-                        )));
-                    }
-                }
+            DexVariant::UniswapV3 => {
+                pools.push(Pool::UniswapV3(UniswapV3Pool::new(
+                    addr,
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    fee,
+                    0,
+                    U256::zero(),
+                    0,
+                    0,
+                    0,
+                )));
             }
         }
     }
 
-    pools
+    Ok(pools)
+}
+
+fn get_field<'a>(
+    map: &'a Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<&'a Value, CheckpointError> {
+    map.get(field)
+        .ok_or(CheckpointError::MissingField { location, field })
+}
+
+fn get_u64(
+    map: &Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<u64, CheckpointError> {
+    get_field(map, field, location)?
+        .as_u64()
+        .ok_or(CheckpointError::InvalidField { location, field })
+}
+
+//Gets an unsigned integer field that must fit in `T`, so that out of range values are rejected instead of truncated
+fn get_uint<T: TryFrom<u64>>(
+    map: &Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<T, CheckpointError> {
+    T::try_from(get_u64(map, field, location)?)
+        .map_err(|_| CheckpointError::InvalidField { location, field })
+}
+
+fn get_str<'a>(
+    map: &'a Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<&'a str, CheckpointError> {
+    get_field(map, field, location)?
+        .as_str()
+        .ok_or(CheckpointError::InvalidField { location, field })
+}
+
+fn get_array<'a>(
+    map: &'a Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<&'a Vec<Value>, CheckpointError> {
+    get_field(map, field, location)?
+        .as_array()
+        .ok_or(CheckpointError::InvalidField { location, field })
+}
+
+fn get_address(
+    map: &Map<String, Value>,
+    field: &'static str,
+    location: CheckpointLocation,
+) -> Result<H160, CheckpointError> {
+    let value = get_str(map, field, location)?;
+
+    H160::from_str(value).map_err(|_| CheckpointError::InvalidAddress {
+        location,
+        field,
+        value: value.to_string(),
+    })
+}
+
+fn get_dex_variant(
+    map: &Map<String, Value>,
+    location: CheckpointLocation,
+) -> Result<DexVariant, CheckpointError> {
+    let dex_variant = get_str(map, "dex_variant", location)?;
+
+    match dex_variant.to_lowercase().as_str() {
+        "uniswapv2" => Ok(DexVariant::UniswapV2),
+        "uniswapv3" => Ok(DexVariant::UniswapV3),
+        _ => Err(CheckpointError::UnrecognizedDexVariant {
+            location,
+            value: dex_variant.to_string(),
+        }),
+    }
 }
 
 pub fn construct_checkpoint(
     dexes: Vec<Dex>,
-    pools: &Vec<Pool>,
+    pools: &[Pool],
     latest_block: u64,
     checkpoint_path: &str,
-) {
-    let mut checkpoint = Map::new();
-
-    //Insert checkpoint_timestamp
+) -> Result<(), CheckpointError> {
     let checkpoint_timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_secs_f32() as u32;
+        .as_secs();
+
+    //The block stored for each dex is the block that it was last synced at, which is where the next sync picks up from
+    let dexes = dexes
+        .into_iter()
+        .map(|dex| match dex {
+            Dex::UniswapV2(mut uniswap_v2_dex) => {
+                uniswap_v2_dex.creation_block = BlockNumber::Number(latest_block.into());
+                Dex::UniswapV2(uniswap_v2_dex)
+            }
+            Dex::UniswapV3(mut uniswap_v3_dex) => {
+                uniswap_v3_dex.creation_block = BlockNumber::Number(latest_block.into());
+                Dex::UniswapV3(uniswap_v3_dex)
+            }
+        })
+        .collect();
 
-    checkpoint.insert(
-        String::from("checkpoint_timestamp"),
-        checkpoint_timestamp.into(),
-    );
+    let checkpoint = CheckpointRef {
+        checkpoint_version: CHECKPOINT_VERSION,
+        checkpoint_timestamp,
+        block_number: latest_block,
+        dexes,
+        pools,
+    };
 
-    checkpoint.insert(String::from("block_number"), latest_block.into());
+    write_checkpoint_atomically(checkpoint_path, serde_json::to_string_pretty(&checkpoint)?)?;
 
-    //Add dexes to checkpoint
-    let mut dexes_array: Vec<Value> = vec![];
-    for dex in dexes {
-        let mut dex_map = Map::new();
+    Ok(())
+}
 
-        dex_map.insert(
-            String::from("factory_address"),
-            format!("{:?}", dex.factory_address()).into(),
-        );
+//Writes the checkpoint to a temporary file and renames it to the checkpoint path
+//so that an interrupted write never leaves a partially written checkpoint behind.
+pub fn write_checkpoint_atomically(checkpoint_path: &str, contents: String) -> std::io::Result<()> {
+    let temp_checkpoint_path = format!("{}.tmp", checkpoint_path);
 
-        dex_map.insert(String::from("block_number"), latest_block.into());
+    std::fs::write(&temp_checkpoint_path, contents)?;
+    std::fs::rename(&temp_checkpoint_path, checkpoint_path)
+}
 
-        match dex {
-            Dex::UniswapV2(uniswap_v2_dex) => {
-                dex_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV2").into(),
-                );
+#[cfg(test)]
+mod tests {
+    use ethers::types::H160;
 
-                dex_map.insert(
-                    String::from("fee"),
-                    format!("{:?}", uniswap_v2_dex.fee).into(),
-                );
-            }
+    use std::{
+        collections::BTreeMap,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
 
-            Dex::UniswapV3(_) => {
-                dex_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV3").into(),
-                );
-            }
+    use ethers::{
+        prelude::ContractError,
+        providers::ProviderError,
+        types::{BlockNumber, U256},
+    };
+    use indicatif::ProgressBar;
+    use serde_json::{json, Value};
+
+    use super::{
+        construct_checkpoint, deconstruct_checkpoint, deconstruct_dex_from_checkpoint,
+        deconstruct_pools_from_checkpoint, dedup_pools_by_address, get_new_pools_since_last_sync,
+        load_pools_from_checkpoint, sync_pools_concurrently,
+        sync_pools_from_checkpoint_with_config, write_checkpoint_atomically, CheckpointSyncConfig,
+    };
+    use crate::{
+        dex::{Dex, DexVariant},
+        errors::{CFMMError, CheckpointError, CheckpointLocation},
+        mock_middleware::MockMiddleware,
+        pool::{
+            uniswap_v3::{TickInfo, TickWindow},
+            Pool, UniswapV2Pool, UniswapV3Pool,
+        },
+        progress::{Progress, ProgressMode},
+        throttle::RequestThrottle,
+    };
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dexes = vec![
+            Dex::new(
+                H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f").unwrap(),
+                DexVariant::UniswapV2,
+                10000835,
+                Some(300),
+            ),
+            Dex::new(
+                H160::from_str("0x1F98431c8aD98523631AE4a59f267346ea31F984").unwrap(),
+                DexVariant::UniswapV3,
+                12369621,
+                None,
+            ),
+        ];
+
+        let pools = vec![
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap(),
+                token_a: H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap(),
+                token_a_decimals: 6,
+                token_b: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+                token_b_decimals: 18,
+                reserve_0: 47092140895915,
+                //Larger than u64::MAX to make sure u128 values are not truncated
+                reserve_1: 28396598565590008529300,
+                fee: 300,
+                last_synced_block: 17000000,
+            }),
+            Pool::UniswapV3(UniswapV3Pool {
+                address: H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640").unwrap(),
+                token_a: H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap(),
+                token_a_decimals: 6,
+                token_b: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+                token_b_decimals: 18,
+                liquidity: 24345663618783208345,
+                sqrt_price: U256::from_dec_str("1948549841328783038463283836468830").unwrap(),
+                fee: 500,
+                tick: 201838,
+                tick_spacing: 10,
+                liquidity_net: -1234,
+                last_synced_block: 17000000,
+                ticks: BTreeMap::from([(
+                    201830,
+                    TickInfo {
+                        liquidity_net: -1234,
+                        initialized: true,
+                    },
+                )]),
+                tick_window: Some(TickWindow {
+                    num_ticks: 10,
+                    lower_tick: 201740,
+                    upper_tick: 201930,
+                }),
+            }),
+        ];
+
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_round_trip.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        construct_checkpoint(dexes.clone(), &pools, 17000000, checkpoint_path).unwrap();
+
+        let (checkpoint_dexes, checkpoint_pools, block_number) =
+            deconstruct_checkpoint(checkpoint_path).unwrap();
+
+        assert_eq!(checkpoint_pools, pools);
+        assert_eq!(block_number, BlockNumber::Number(17000000.into()));
+
+        //Each dex is stored with the block that it was synced at
+        assert_eq!(checkpoint_dexes.len(), dexes.len());
+        for (checkpoint_dex, dex) in checkpoint_dexes.iter().zip(dexes.iter()) {
+            assert_eq!(checkpoint_dex.factory_address(), dex.factory_address());
+            assert_eq!(
+                checkpoint_dex.creation_block(),
+                BlockNumber::Number(17000000.into())
+            );
         }
 
-        dexes_array.push(Value::Object(dex_map));
+        let (_, loaded_pools) = load_pools_from_checkpoint(checkpoint_path).unwrap();
+        assert_eq!(loaded_pools, pools);
+
+        std::fs::remove_file(checkpoint_path).unwrap();
     }
 
-    checkpoint.insert(String::from("dexes"), dexes_array.into());
+    #[test]
+    fn test_load_legacy_checkpoint() {
+        let checkpoint = json!({
+            "checkpoint_timestamp": 1680000000,
+            "block_number": 17000000,
+            "dexes": [{
+                "factory_address": "0x5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f",
+                "block_number": 17000000,
+                "dex_variant": "UniswapV2",
+                "fee": "300"
+            }],
+            "pools": [pool_value()]
+        });
+
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_legacy_checkpoint.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        std::fs::write(checkpoint_path, checkpoint.to_string()).unwrap();
+
+        let (dexes, pools) = load_pools_from_checkpoint(checkpoint_path).unwrap();
+
+        assert_eq!(dexes.len(), 1);
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].fee(), 300);
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+    }
 
-    //Insert pools into checkpoint
-    let mut pools_array: Vec<Value> = vec![];
-    for pool in pools {
-        let mut pool_map = Map::new();
+    #[test]
+    fn test_unsupported_checkpoint_version() {
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_checkpoint_version.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        std::fs::write(
+            checkpoint_path,
+            json!({ "checkpoint_version": 99 }).to_string(),
+        )
+        .unwrap();
 
-        match pool {
-            Pool::UniswapV2(uniswap_v2_pool) => {
-                pool_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV2").into(),
-                );
+        assert!(matches!(
+            deconstruct_checkpoint(checkpoint_path),
+            Err(CheckpointError::UnsupportedVersion(99))
+        ));
 
-                pool_map.insert(
-                    String::from("address"),
-                    format!("{:?}", uniswap_v2_pool.address).into(),
-                );
+        std::fs::remove_file(checkpoint_path).unwrap();
+    }
 
-                pool_map.insert(
-                    String::from("token_a"),
-                    format!("{:?}", uniswap_v2_pool.token_a).into(),
-                );
+    fn pool_value() -> Value {
+        json!({
+            "dex_variant": "UniswapV2",
+            "address": "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc",
+            "token_a": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "token_a_decimals": 6,
+            "token_b": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "token_b_decimals": 18,
+            "fee": 300
+        })
+    }
 
-                pool_map.insert(
-                    String::from("token_a_decimals"),
-                    uniswap_v2_pool.token_a_decimals.into(),
-                );
+    #[test]
+    fn test_deconstruct_pools_missing_field() {
+        let mut pool_without_token_b = pool_value();
+        pool_without_token_b
+            .as_object_mut()
+            .unwrap()
+            .remove("token_b");
+
+        let result = deconstruct_pools_from_checkpoint(&[pool_value(), pool_without_token_b]);
+
+        assert!(matches!(
+            result,
+            Err(CheckpointError::MissingField {
+                location: CheckpointLocation::Pool(1),
+                field: "token_b",
+            })
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_b"),
-                    format!("{:?}", uniswap_v2_pool.token_b).into(),
-                );
+    #[test]
+    fn test_deconstruct_pools_out_of_range_field() {
+        let mut pool_with_large_decimals = pool_value();
+        pool_with_large_decimals["token_b_decimals"] = json!(256);
+
+        assert!(matches!(
+            deconstruct_pools_from_checkpoint(&[pool_value(), pool_with_large_decimals]),
+            Err(CheckpointError::InvalidField {
+                location: CheckpointLocation::Pool(1),
+                field: "token_b_decimals",
+            })
+        ));
+
+        let mut pool_with_large_fee = pool_value();
+        pool_with_large_fee["fee"] = json!(u64::from(u32::MAX) + 1);
+
+        assert!(matches!(
+            deconstruct_pools_from_checkpoint(&[pool_with_large_fee]),
+            Err(CheckpointError::InvalidField {
+                location: CheckpointLocation::Pool(0),
+                field: "fee",
+            })
+        ));
+    }
 
-                pool_map.insert(
-                    String::from("token_b_decimals"),
-                    uniswap_v2_pool.token_b_decimals.into(),
-                );
+    #[test]
+    fn test_deconstruct_pools_invalid_address() {
+        let mut pool = pool_value();
+        pool["token_a"] = json!("0xnotanaddress");
+
+        match deconstruct_pools_from_checkpoint(&[pool]) {
+            Err(CheckpointError::InvalidAddress {
+                location,
+                field,
+                value,
+            }) => {
+                assert_eq!(location, CheckpointLocation::Pool(0));
+                assert_eq!(field, "token_a");
+                assert_eq!(value, "0xnotanaddress");
+            }
+            other => panic!("Expected an invalid address error, got {:?}", other),
+        }
+    }
 
-                pool_map.insert(String::from("fee"), uniswap_v2_pool.fee.into());
+    #[test]
+    fn test_deconstruct_unrecognized_dex_variant() {
+        let mut pool = pool_value();
+        pool["dex_variant"] = json!("Curve");
+
+        assert!(matches!(
+            deconstruct_pools_from_checkpoint(&[pool]),
+            Err(CheckpointError::UnrecognizedDexVariant {
+                location: CheckpointLocation::Pool(0),
+                value,
+            }) if value == "Curve"
+        ));
+
+        let dex = json!({
+            "dex_variant": "Balancer",
+            "factory_address": "0x5c69bee701ef814a2b6a3edd4b1652cb9cc5aa6f",
+            "block_number": 10000835
+        });
+
+        assert!(matches!(
+            deconstruct_dex_from_checkpoint(dex.as_object().unwrap(), 3),
+            Err(CheckpointError::UnrecognizedDexVariant {
+                location: CheckpointLocation::Dex(3),
+                ..
+            })
+        ));
+    }
 
-                pools_array.push(pool_map.into());
-            }
+    #[test]
+    fn test_deconstruct_checkpoint_io_and_json_errors() {
+        assert!(matches!(
+            deconstruct_checkpoint("this/checkpoint/does/not/exist.json"),
+            Err(CheckpointError::IoError(_))
+        ));
 
-            Pool::UniswapV3(uniswap_v3_pool) => {
-                pool_map.insert(
-                    String::from("dex_variant"),
-                    String::from("UniswapV3").into(),
-                );
+        let checkpoint_path = std::env::temp_dir().join("cfmms_test_invalid_checkpoint.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+        std::fs::write(checkpoint_path, "{ not json").unwrap();
 
-                pool_map.insert(
-                    String::from("address"),
-                    format!("{:?}", uniswap_v3_pool.address).into(),
-                );
+        assert!(matches!(
+            deconstruct_checkpoint(checkpoint_path),
+            Err(CheckpointError::SerdeError(_))
+        ));
 
-                pool_map.insert(
-                    String::from("token_a"),
-                    format!("{:?}", uniswap_v3_pool.token_a).into(),
-                );
+        std::fs::remove_file(checkpoint_path).unwrap();
+    }
 
-                pool_map.insert(
-                    String::from("token_a_decimals"),
-                    uniswap_v3_pool.token_a_decimals.into(),
-                );
+    fn v2_pool(address: u64) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            reserve_0: 1,
+            reserve_1: 1,
+            ..Default::default()
+        })
+    }
 
-                pool_map.insert(
-                    String::from("token_b"),
-                    format!("{:?}", uniswap_v3_pool.token_b).into(),
-                );
+    #[tokio::test]
+    async fn test_sync_pools_from_checkpoint_with_config_returns_failed_pools() {
+        let checkpoint_path = std::env::temp_dir()
+            .join("cfmms_test_sync_pools_from_checkpoint_with_config_returns_failed_pools.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        let (token_a, token_b) = (H160::from_low_u64_be(10), H160::from_low_u64_be(11));
+        let pools = (1..=2)
+            .map(|address| {
+                Pool::UniswapV2(UniswapV2Pool {
+                    address: H160::from_low_u64_be(address),
+                    token_a,
+                    token_b,
+                    reserve_0: 1,
+                    reserve_1: 1,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<Pool>>();
+
+        //The dex is synced to the current block, so only the checkpoint pools are synced
+        let dexes = vec![Dex::new(
+            H160::from_low_u64_be(1),
+            DexVariant::UniswapV2,
+            0,
+            None,
+        )];
+        construct_checkpoint(dexes, &pools, 100, checkpoint_path).unwrap();
+
+        //The reserves for pool 2 could not be synced, so its getReserves call fails
+        let middleware =
+            Arc::new(MockMiddleware::new(100).with_reserves(H160::from_low_u64_be(1), 5, 5));
+
+        let config = CheckpointSyncConfig {
+            progress_config: ProgressMode::Quiet.into(),
+            ..Default::default()
+        };
 
-                pool_map.insert(
-                    String::from("token_b_decimals"),
-                    uniswap_v3_pool.token_b_decimals.into(),
+        let (_, synced_pools, failed_pools) =
+            sync_pools_from_checkpoint_with_config(checkpoint_path, config, middleware)
+                .await
+                .unwrap();
+
+        assert_eq!(failed_pools, vec![H160::from_low_u64_be(2)]);
+
+        //Failed pools keep their previous state
+        let (_, checkpoint_pools) = load_pools_from_checkpoint(checkpoint_path).unwrap();
+        assert_eq!(checkpoint_pools, synced_pools);
+        match (&synced_pools[0], &synced_pools[1]) {
+            (Pool::UniswapV2(synced_pool), Pool::UniswapV2(failed_pool)) => {
+                assert_eq!(
+                    (synced_pool.reserve_0, synced_pool.last_synced_block),
+                    (5, 100)
                 );
+                assert_eq!(
+                    (failed_pool.reserve_0, failed_pool.last_synced_block),
+                    (1, 0)
+                );
+            }
+            _ => panic!("Expected Uniswap V2 pools"),
+        }
 
-                pool_map.insert(String::from("fee"), uniswap_v3_pool.fee.into());
+        std::fs::remove_file(checkpoint_path).unwrap();
+    }
 
-                pools_array.push(pool_map.into());
-            }
+    #[tokio::test]
+    async fn test_sync_pools_concurrently() {
+        //The pool at address 3 has no mocked reserves, so decoding its reserves fails and it keeps its previous state
+        let middleware = Arc::new((1..=5).filter(|address| *address != 3).fold(
+            MockMiddleware::new(100),
+            |middleware, address| {
+                middleware.with_reserves(H160::from_low_u64_be(address), address as u128 * 10, 7)
+            },
+        ));
+
+        let pools = (1..=5).map(v2_pool).collect::<Vec<Pool>>();
+        let progress_bar = ProgressBar::hidden();
+
+        let synced_pools = sync_pools_concurrently(
+            pools,
+            100.into(),
+            2,
+            Arc::new(Mutex::new(RequestThrottle::new(0))),
+            progress_bar.clone(),
+            middleware.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(progress_bar.position(), 5);
+        //The block number is passed in, so there is a single request per pool
+        assert_eq!(middleware.requests(), 5);
+
+        let synced_pools = synced_pools
+            .iter()
+            .map(|pool| match pool {
+                Pool::UniswapV2(pool) => (
+                    pool.address.to_low_u64_be(),
+                    pool.reserve_0,
+                    pool.last_synced_block,
+                ),
+                Pool::UniswapV3(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            synced_pools,
+            vec![
+                (1, 10, 100),
+                (2, 20, 100),
+                (3, 1, 0),
+                (4, 40, 100),
+                (5, 50, 100)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_pools_concurrently_aborts_on_middleware_error() {
+        let middleware =
+            Arc::new(MockMiddleware::new(100).with_reserves(H160::from_low_u64_be(1), 10, 7));
+        middleware.push_error(ProviderError::CustomError(String::from(
+            "connection closed",
+        )));
+
+        let result = sync_pools_concurrently(
+            (1..=3).map(v2_pool).collect(),
+            100.into(),
+            1,
+            Arc::new(Mutex::new(RequestThrottle::new(0))),
+            ProgressBar::hidden(),
+            middleware.clone(),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(CFMMError::ContractError(
+                ContractError::MiddlewareError { .. }
+            ))
+        ));
+        //No further pools are synced once the error is encountered
+        assert_eq!(middleware.requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_new_pools_since_last_sync_rejects_block_tags() {
+        let mut dex = Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 0, None);
+        if let Dex::UniswapV2(uniswap_v2_dex) = &mut dex {
+            uniswap_v2_dex.creation_block = BlockNumber::Latest;
         }
+
+        let result = get_new_pools_since_last_sync(
+            &[
+                Dex::new(H160::from_low_u64_be(2), DexVariant::UniswapV2, 100, None),
+                dex,
+            ],
+            100.into(),
+            100000,
+            Arc::new(Mutex::new(RequestThrottle::new(0))),
+            Progress::new(ProgressMode::Quiet.into()),
+            Arc::new(MockMiddleware::new(100)),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(CheckpointError::InvalidField {
+                location: CheckpointLocation::Dex(1),
+                field: "block_number",
+            })
+        ));
     }
 
-    checkpoint.insert(String::from("pools"), pools_array.into());
+    #[test]
+    fn test_dedup_pools_by_address() {
+        let pools = vec![
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(1),
+                reserve_0: 1,
+                ..Default::default()
+            }),
+            Pool::UniswapV3(UniswapV3Pool {
+                address: H160::from_low_u64_be(2),
+                ..Default::default()
+            }),
+            Pool::UniswapV2(UniswapV2Pool {
+                address: H160::from_low_u64_be(1),
+                reserve_0: 2,
+                ..Default::default()
+            }),
+        ];
+
+        let pools = dedup_pools_by_address(pools);
+
+        assert_eq!(pools.len(), 2);
+        match &pools[0] {
+            Pool::UniswapV2(uniswap_v2_pool) => assert_eq!(uniswap_v2_pool.reserve_0, 1),
+            Pool::UniswapV3(_) => panic!("Expected the first pool to be kept"),
+        }
+        assert_eq!(pools[1].address(), H160::from_low_u64_be(2));
+    }
 
-    std::fs::write(
-        checkpoint_path,
-        serde_json::to_string_pretty(&checkpoint).unwrap(),
-    )
-    .unwrap();
+    #[test]
+    fn test_write_checkpoint_atomically() {
+        let checkpoint_path =
+            std::env::temp_dir().join("cfmms_test_write_checkpoint_atomically.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        write_checkpoint_atomically(checkpoint_path, String::from("old")).unwrap();
+        write_checkpoint_atomically(checkpoint_path, String::from("new")).unwrap();
+
+        assert_eq!(std::fs::read_to_string(checkpoint_path).unwrap(), "new");
+        assert!(!std::path::Path::new(&format!("{}.tmp", checkpoint_path)).exists());
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+    }
 }
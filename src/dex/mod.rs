@@ -4,13 +4,14 @@ use ethers::{
     providers::Middleware,
     types::{BlockNumber, Filter, Log, ValueOrArray, H160, H256, U64},
 };
+use futures::FutureExt;
 use indicatif::ProgressBar;
 
 use crate::{
     abi, batch_requests,
     errors::CFMMError,
     pool::{Pool, UniswapV2Pool, UniswapV3Pool},
-    throttle::RequestThrottle,
+    throttle::{increment_or_sleep, RequestThrottle},
 };
 
 use serde::{Deserialize, Serialize};
@@ -20,7 +21,7 @@ use self::{uniswap_v2::UniswapV2Dex, uniswap_v3::UniswapV3Dex};
 pub mod uniswap_v2;
 pub mod uniswap_v3;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Dex {
     UniswapV2(UniswapV2Dex),
     UniswapV3(UniswapV3Dex),
@@ -33,7 +34,7 @@ impl Dex {
         creation_block: u64,
         fee: Option<u64>,
     ) -> Dex {
-        let fee = if let Some(fee) = fee { fee } else { 300 };
+        let fee = fee.unwrap_or(300);
 
         match dex_variant {
             DexVariant::UniswapV2 => Dex::UniswapV2(UniswapV2Dex::new(
@@ -92,10 +93,23 @@ impl Dex {
                     .await
             }
             Dex::UniswapV3(_) => {
-                let current_block = middleware
-                    .get_block_number()
-                    .await
-                    .map_err(CFMMError::MiddlewareError)?;
+                let retry_policy = request_throttle
+                    .lock()
+                    .expect("Error when acquiring request throttle mutex lock")
+                    .retry_policy();
+                let current_block = retry_policy
+                    .retry(|| {
+                        let (middleware, request_throttle) =
+                            (middleware.clone(), &request_throttle);
+                        async move {
+                            increment_or_sleep(request_throttle, 1).await;
+                            middleware
+                                .get_block_number()
+                                .await
+                                .map_err(CFMMError::MiddlewareError)
+                        }
+                    })
+                    .await?;
 
                 self.get_all_pools_from_logs(
                     current_block.into(),
@@ -110,47 +124,78 @@ impl Dex {
     }
 
     //Gets all pool data and sync reserves
-    pub async fn get_all_pool_data<M: Middleware>(
+    pub async fn get_all_pool_data<M: 'static + Middleware>(
         &self,
         pools: &mut [Pool],
         request_throttle: Arc<Mutex<RequestThrottle>>,
         progress_bar: ProgressBar,
         middleware: Arc<M>,
     ) -> Result<(), CFMMError<M>> {
+        self.get_all_pool_data_at_block(pools, None, request_throttle, progress_bar, middleware)
+            .await
+    }
+
+    //Gets all pool data and sync reserves at `block_number`, or at the latest block if `None`.
+    //Pools that are populated have their last synced block set to `block_number`.
+    pub async fn get_all_pool_data_at_block<M: 'static + Middleware>(
+        &self,
+        pools: &mut [Pool],
+        block_number: Option<U64>,
+        request_throttle: Arc<Mutex<RequestThrottle>>,
+        progress_bar: ProgressBar,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let retry_policy = request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
+
         match self {
             Dex::UniswapV2(_) => {
                 let step = 127; //Max batch size for call
                 for pools in pools.chunks_mut(step) {
-                    request_throttle
-                        .lock()
-                        .expect("Error when acquiring request throttle mutex lock")
-                        .increment_or_sleep(1);
-
-                    batch_requests::uniswap_v2::get_pool_data_batch_request(
-                        pools,
-                        middleware.clone(),
-                    )
-                    .await?;
+                    retry_policy
+                        .retry_with(pools, |pools| {
+                            let request_throttle = request_throttle.clone();
+                            let middleware = middleware.clone();
+                            async move {
+                                increment_or_sleep(&request_throttle, 1).await;
+                                batch_requests::uniswap_v2::get_pool_data_batch_request(
+                                    pools,
+                                    block_number,
+                                    middleware,
+                                )
+                                .await
+                            }
+                            .boxed()
+                        })
+                        .await?;
 
-                    progress_bar.inc(step as u64);
+                    progress_bar.inc(pools.len() as u64);
                 }
             }
 
             Dex::UniswapV3(_) => {
                 let step = 76; //Max batch size for call
                 for pools in pools.chunks_mut(step) {
-                    request_throttle
-                        .lock()
-                        .expect("Error when acquiring request throttle mutex lock")
-                        .increment_or_sleep(1);
-
-                    batch_requests::uniswap_v3::get_pool_data_batch_request(
-                        pools,
-                        middleware.clone(),
-                    )
-                    .await?;
+                    retry_policy
+                        .retry_with(pools, |pools| {
+                            let request_throttle = request_throttle.clone();
+                            let middleware = middleware.clone();
+                            async move {
+                                increment_or_sleep(&request_throttle, 1).await;
+                                batch_requests::uniswap_v3::get_pool_data_batch_request(
+                                    pools,
+                                    block_number,
+                                    middleware,
+                                )
+                                .await
+                            }
+                            .boxed()
+                        })
+                        .await?;
 
-                    progress_bar.inc(step as u64);
+                    progress_bar.inc(pools.len() as u64);
                 }
             }
         }
@@ -320,8 +365,13 @@ impl Dex {
 
         let mut aggregated_pairs: Vec<Pool> = vec![];
 
+        let retry_policy = request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
+
         //Initialize the progress bar message
-        progress_bar.set_length(current_block - from_block);
+        progress_bar.inc_length(current_block - from_block);
 
         //For each block within the range, get all pairs asynchronously
         for from_block in (from_block..=current_block).step_by(step) {
@@ -332,22 +382,26 @@ impl Dex {
             //Get pair created event logs within the block range
             let to_block = from_block + step as u64;
 
-            //Update the throttle
-            request_throttle
-                .lock()
-                .expect("Error when acquiring request throttle mutex lock")
-                .increment_or_sleep(1);
-
-            let logs = provider
-                .get_logs(
-                    &Filter::new()
-                        .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                        .address(self.factory_address())
-                        .from_block(BlockNumber::Number(U64([from_block])))
-                        .to_block(BlockNumber::Number(U64([to_block]))),
-                )
-                .await
-                .map_err(CFMMError::MiddlewareError)?;
+            let filter = Filter::new()
+                .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+                .address(self.factory_address())
+                .from_block(BlockNumber::Number(U64([from_block])))
+                .to_block(BlockNumber::Number(U64([to_block])));
+
+            let logs = retry_policy
+                .retry(|| {
+                    let (provider, filter, request_throttle) =
+                        (&provider, &filter, &request_throttle);
+                    async move {
+                        //Update the throttle for every attempt
+                        increment_or_sleep(request_throttle, 1).await;
+                        provider
+                            .get_logs(filter)
+                            .await
+                            .map_err(CFMMError::MiddlewareError)
+                    }
+                })
+                .await?;
 
             //For each pair created log, create a new Pair type and add it to the pairs vec
             for log in logs {
@@ -355,8 +409,8 @@ impl Dex {
                 aggregated_pairs.push(pool);
             }
 
-            //Increment the progress bar by the step
-            progress_bar.inc(step as u64);
+            //Increment the progress bar by the blocks covered in this step
+            progress_bar.inc(to_block.min(current_block) - from_block);
         }
 
         Ok(aggregated_pairs)
@@ -384,11 +438,18 @@ impl Dex {
 
         let mut aggregated_pairs: Vec<Pool> = vec![];
 
+        let retry_policy = request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
+
         //Initialize the progress bar message
-        progress_bar.set_length(to_block - from_block);
+        progress_bar.inc_length(to_block - from_block);
+
+        let range_end = to_block;
 
         //For each block within the range, get all pairs asynchronously
-        for from_block in (from_block..=to_block).step_by(step) {
+        for from_block in (from_block..=range_end).step_by(step) {
             let request_throttle = request_throttle.clone();
             let provider = middleware.clone();
             let progress_bar = progress_bar.clone();
@@ -396,22 +457,26 @@ impl Dex {
             //Get pair created event logs within the block range
             let to_block = from_block + step as u64;
 
-            //Update the throttle
-            request_throttle
-                .lock()
-                .expect("Error when acquiring request throttle mutex lock")
-                .increment_or_sleep(1);
-
-            let logs = provider
-                .get_logs(
-                    &Filter::new()
-                        .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                        .address(self.factory_address())
-                        .from_block(BlockNumber::Number(U64([from_block])))
-                        .to_block(BlockNumber::Number(U64([to_block]))),
-                )
-                .await
-                .map_err(CFMMError::MiddlewareError)?;
+            let filter = Filter::new()
+                .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+                .address(self.factory_address())
+                .from_block(BlockNumber::Number(U64([from_block])))
+                .to_block(BlockNumber::Number(U64([to_block])));
+
+            let logs = retry_policy
+                .retry(|| {
+                    let (provider, filter, request_throttle) =
+                        (&provider, &filter, &request_throttle);
+                    async move {
+                        //Update the throttle for every attempt
+                        increment_or_sleep(request_throttle, 1).await;
+                        provider
+                            .get_logs(filter)
+                            .await
+                            .map_err(CFMMError::MiddlewareError)
+                    }
+                })
+                .await?;
 
             //For each pair created log, create a new Pair type and add it to the pairs vec
             for log in logs {
@@ -419,8 +484,8 @@ impl Dex {
                 aggregated_pairs.push(pool);
             }
 
-            //Increment the progress bar by the step
-            progress_bar.inc(step as u64);
+            //Increment the progress bar by the blocks covered in this step
+            progress_bar.inc(to_block.min(range_end) - from_block);
         }
 
         Ok(aggregated_pairs)
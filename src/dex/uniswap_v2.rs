@@ -12,16 +12,32 @@ use crate::{
     abi, batch_requests,
     errors::CFMMError,
     pool::{Pool, UniswapV2Pool},
-    throttle::RequestThrottle,
+    throttle::{increment_or_sleep, RequestThrottle},
 };
 
 use super::DexVariant;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV2Dex {
     pub factory_address: H160,
     pub creation_block: BlockNumber,
     pub fee: u64,
+    #[serde(default)]
+    pub fee_source: FeeSource,
+}
+
+//Where the swap fee for the dex comes from. Canonical Uniswap V2 deployments have an immutable fee,
+//while some forks can change the fee through the factory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum FeeSource {
+    #[default]
+    Immutable,
+    //A view function on the factory that takes no arguments and returns the swap fee as a uint,
+    //where `fee_denominator` is the value that represents a 100% fee
+    FactoryGetter {
+        selector: [u8; 4],
+        fee_denominator: u64,
+    },
 }
 
 pub const PAIR_CREATED_EVENT_SIGNATURE: H256 = H256([
@@ -35,9 +51,19 @@ impl UniswapV2Dex {
             factory_address,
             creation_block,
             fee,
+            fee_source: FeeSource::Immutable,
         }
     }
 
+    pub fn with_fee_source(mut self, fee_source: FeeSource) -> UniswapV2Dex {
+        self.fee_source = fee_source;
+        self
+    }
+
+    pub fn has_mutable_fee(&self) -> bool {
+        self.fee_source != FeeSource::Immutable
+    }
+
     pub const fn pool_created_event_signature(&self) -> H256 {
         PAIR_CREATED_EVENT_SIGNATURE
     }
@@ -67,6 +93,7 @@ impl UniswapV2Dex {
             reserve_0: 0,
             reserve_1: 0,
             fee: 300,
+            last_synced_block: 0,
         }))
     }
 
@@ -78,9 +105,19 @@ impl UniswapV2Dex {
     ) -> Result<Vec<Pool>, CFMMError<M>> {
         let factory = abi::IUniswapV2Factory::new(self.factory_address, middleware.clone());
 
-        let pairs_length: U256 = factory.all_pairs_length().call().await?;
+        let retry_policy = request_throttle
+            .lock()
+            .expect("Could not acquire mutex")
+            .retry_policy();
+        let pairs_length: U256 = retry_policy
+            .retry(|| {
+                let factory = factory.clone();
+                async move { Ok(factory.all_pairs_length().call().await?) }
+            })
+            .await?;
+
         //Initialize the progress bar message
-        progress_bar.set_length(pairs_length.as_u64());
+        progress_bar.inc_length(pairs_length.as_u64());
 
         let mut pairs = vec![];
         let step = 766; //max batch size for this call until codesize is too large
@@ -92,19 +129,23 @@ impl UniswapV2Dex {
         };
 
         for _ in (0..pairs_length.as_u128()).step_by(step) {
-            request_throttle
-                .lock()
-                .expect("Could not acquire mutex")
-                .increment_or_sleep(1);
-
             pairs.append(
-                &mut batch_requests::uniswap_v2::get_pairs_batch_request(
-                    self.factory_address,
-                    idx_from,
-                    idx_to,
-                    middleware.clone(),
-                )
-                .await?,
+                &mut retry_policy
+                    .retry(|| {
+                        let request_throttle = &request_throttle;
+                        let middleware = middleware.clone();
+                        async move {
+                            increment_or_sleep(request_throttle, 1).await;
+                            batch_requests::uniswap_v2::get_pairs_batch_request(
+                                self.factory_address,
+                                idx_from,
+                                idx_to,
+                                middleware,
+                            )
+                            .await
+                        }
+                    })
+                    .await?,
             );
 
             idx_from = idx_to;
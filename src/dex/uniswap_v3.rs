@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     panic::resume_unwind,
     sync::{Arc, Mutex},
 };
@@ -14,12 +15,12 @@ use serde::{Deserialize, Serialize};
 use crate::{
     errors::CFMMError,
     pool::{Pool, UniswapV3Pool},
-    throttle::RequestThrottle,
+    throttle::{increment_or_sleep, RequestThrottle},
 };
 
 use super::DexVariant;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV3Dex {
     pub factory_address: H160,
     pub creation_block: BlockNumber,
@@ -71,6 +72,9 @@ impl UniswapV3Dex {
             tick_spacing: 0,
             tick: 0,
             liquidity_net: 0,
+            last_synced_block: 0,
+            ticks: BTreeMap::new(),
+            tick_window: None,
         }))
     }
 
@@ -96,9 +100,14 @@ impl UniswapV3Dex {
             .expect("Error using converting current block as number")
             .as_u64();
 
-        //Initialize the progress bar message
-        progress_bar.set_length(current_block - from_block);
-        progress_bar.set_message(format!("Getting all pools from: {}", self.factory_address));
+        //The stage message is set by the caller, which also keeps the aggregate message in minimal mode
+        progress_bar.inc_length(current_block - from_block);
+
+        //Clones of the retry policy share the same backoff, so a rate limited task also delays the other tasks
+        let retry_policy = request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
 
         //Init a new vec to keep track of tasks
         let mut handles = vec![];
@@ -108,6 +117,7 @@ impl UniswapV3Dex {
             let request_throttle = request_throttle.clone();
             let provider = middleware.clone();
             let progress_bar = progress_bar.clone();
+            let retry_policy = retry_policy.clone();
 
             //Spawn a new task to get pair created events from the block range
             handles.push(tokio::spawn(async move {
@@ -116,22 +126,26 @@ impl UniswapV3Dex {
                 //Get pair created event logs within the block range
                 let to_block = from_block + step as u64;
 
-                //Update the throttle
-                request_throttle
-                    .lock()
-                    .expect("Error when acquiring request throttle mutex lock")
-                    .increment_or_sleep(1);
-
-                let logs = provider
-                    .get_logs(
-                        &ethers::types::Filter::new()
-                            .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
-                            .address(self.factory_address)
-                            .from_block(BlockNumber::Number(ethers::types::U64([from_block])))
-                            .to_block(BlockNumber::Number(ethers::types::U64([to_block]))),
-                    )
-                    .await
-                    .map_err(CFMMError::MiddlewareError)?;
+                let filter = ethers::types::Filter::new()
+                    .topic0(ValueOrArray::Value(self.pool_created_event_signature()))
+                    .address(self.factory_address)
+                    .from_block(BlockNumber::Number(ethers::types::U64([from_block])))
+                    .to_block(BlockNumber::Number(ethers::types::U64([to_block])));
+
+                let logs = retry_policy
+                    .retry(|| {
+                        let (provider, filter, request_throttle) =
+                            (&provider, &filter, &request_throttle);
+                        async move {
+                            //Update the throttle for every attempt
+                            increment_or_sleep(request_throttle, 1).await;
+                            provider
+                                .get_logs(filter)
+                                .await
+                                .map_err(CFMMError::MiddlewareError)
+                        }
+                    })
+                    .await?;
 
                 //For each pair created log, create a new Pair type and add it to the pairs vec
                 for log in logs {
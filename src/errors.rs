@@ -1,8 +1,10 @@
 use std::fmt;
 
+use ethers::contract::MulticallError;
 use ethers::prelude::{AbiError, ContractError};
 use ethers::providers::{Middleware, ProviderError};
 use ethers::types::{H160, U256};
+use indicatif::style::TemplateError;
 use thiserror::Error;
 use tokio::task::JoinError;
 use uniswap_v3_math::error::UniswapV3MathError;
@@ -18,10 +20,16 @@ where
     ProviderError(#[from] ProviderError),
     #[error("Contract error")]
     ContractError(#[from] ContractError<M>),
+    #[error("Multicall error")]
+    MulticallError(#[from] MulticallError<M>),
     #[error("ABI Codec error")]
     ABICodecError(#[from] AbiError),
     #[error("Eth ABI error")]
     EthABIError(#[from] ethers::abi::Error),
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+    #[error("Serde JSON error")]
+    SerdeJsonError(#[from] serde_json::Error),
     #[error("Join error")]
     JoinError(#[from] JoinError),
     #[error("Uniswap V3 math error")]
@@ -34,12 +42,105 @@ where
     SyncError(H160),
     #[error("Error when getting pool data")]
     PoolDataError,
+    #[error("Factory {0:?} did not return a valid fee")]
+    InvalidFactoryFee(H160),
     #[error("Arithmetic error")]
     ArithmeticError(#[from] ArithmeticError),
     #[error("No initialized ticks during v3 swap simulation")]
     NoInitializedTicks,
     #[error("No liquidity net found during v3 swap simulation")]
     NoLiquidityNet,
+    #[error("Blocking API can not be called from within an async runtime")]
+    BlockingInAsyncContext,
+    #[error("Stale quote")]
+    StaleQuote(#[from] StaleQuote),
+    #[error("Swap simulation error")]
+    SwapSimulationError(#[from] SwapSimulationError),
+    #[error("Checkpoint error")]
+    CheckpointError(#[from] CheckpointError),
+    #[error("Rate limit exceeded after {retries} retries: {error}")]
+    RateLimitExceeded {
+        retries: u32,
+        #[source]
+        error: RateLimitedError<M>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("Missing field `{field}` in {location}")]
+    MissingField {
+        location: CheckpointLocation,
+        field: &'static str,
+    },
+    #[error("Invalid value for field `{field}` in {location}")]
+    InvalidField {
+        location: CheckpointLocation,
+        field: &'static str,
+    },
+    #[error("Invalid address {value:?} for field `{field}` in {location}")]
+    InvalidAddress {
+        location: CheckpointLocation,
+        field: &'static str,
+        value: String,
+    },
+    #[error("Unrecognized dex variant {value:?} in {location}")]
+    UnrecognizedDexVariant {
+        location: CheckpointLocation,
+        value: String,
+    },
+    #[error("Unsupported checkpoint version {0}")]
+    UnsupportedVersion(u64),
+    #[error("IO error")]
+    IoError(#[from] std::io::Error),
+    #[error("Serde JSON error")]
+    SerdeError(#[from] serde_json::Error),
+}
+
+//Where in the checkpoint a `CheckpointError` occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointLocation {
+    Root,
+    Dex(usize),
+    Pool(usize),
+}
+
+impl fmt::Display for CheckpointLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointLocation::Root => write!(f, "checkpoint"),
+            CheckpointLocation::Dex(index) => write!(f, "dex {}", index),
+            CheckpointLocation::Pool(index) => write!(f, "pool {}", index),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProgressConfigError {
+    #[error("Invalid progress bar template")]
+    InvalidTemplate(#[from] TemplateError),
+    #[error("At least 2 progress chars are required")]
+    NotEnoughProgressChars,
+}
+
+#[derive(Error, Debug)]
+pub enum SwapSimulationError {
+    #[error("Tick data for word {word_position} of pool {pool:?} is not available")]
+    TickDataOutOfRange { pool: H160, word_position: i16 },
+    #[error("Token {token:?} is not in pool {pool:?}")]
+    TokenNotInPool { pool: H160, token: H160 },
+    #[error("Fee {fee} of pool {pool:?} is greater than 100%")]
+    InvalidFee { pool: H160, fee: u32 },
+    #[error("Uniswap V3 math error")]
+    UniswapV3MathError(#[from] UniswapV3MathError),
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Quote state at block {state_block} is older than {max_age_blocks} blocks at block {current_block}")]
+pub struct StaleQuote {
+    pub state_block: u64,
+    pub current_block: u64,
+    pub max_age_blocks: u64,
 }
 
 #[derive(Error, Debug)]
@@ -48,6 +149,7 @@ pub enum ArithmeticError {
     RoundingError,
     YIsZero,
     SqrtPriceOverflow,
+    UniswapV3MathError(#[from] UniswapV3MathError),
 }
 
 impl std::fmt::Display for ArithmeticError {
@@ -55,3 +157,26 @@ impl std::fmt::Display for ArithmeticError {
         write!(f, "")
     }
 }
+
+//The error returned by the last rate limited call, its Display and source are those of the wrapped error.
+//Display and Error are implemented by hand because the bounds that thiserror derives for a boxed `CFMMError` field are recursive.
+#[derive(Debug)]
+pub struct RateLimitedError<M: Middleware>(pub Box<CFMMError<M>>);
+
+impl<M: Middleware> RateLimitedError<M> {
+    pub fn into_inner(self) -> CFMMError<M> {
+        *self.0
+    }
+}
+
+impl<M: Middleware> fmt::Display for RateLimitedError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<M: 'static + Middleware> std::error::Error for RateLimitedError<M> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(self.0.as_ref())
+    }
+}
@@ -0,0 +1,685 @@
+//Monitors the swap fee of Uniswap V2 forks that can change their fee through the factory.
+//Dexes with an immutable fee are never registered, so the monitor does no work for canonical Uniswap deployments.
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ethers::{
+    abi::ParamType,
+    contract::{Multicall, MULTICALL_ADDRESS},
+    providers::Middleware,
+    types::{Bytes, TransactionRequest, H160, U256},
+};
+use tokio::sync::{broadcast, RwLock};
+
+use crate::{
+    abi,
+    dex::{
+        uniswap_v2::{FeeSource, UniswapV2Dex},
+        Dex,
+    },
+    errors::CFMMError,
+    pool::{uniswap_v2::POOL_FEE_DENOMINATOR, Pool},
+    throttle::{increment_or_sleep, RequestThrottle},
+};
+
+//Max number of pair lookups per multicall
+const LOOKUP_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeChanged {
+    //Factory address of the dex
+    pub dex: H160,
+    pub old: u32,
+    pub new: u32,
+}
+
+struct WatchedDex {
+    dex: UniswapV2Dex,
+    pools: HashSet<H160>,
+    //Pools that were matched to the dex but do not have the current factory fee set yet
+    pending_pools: HashSet<H160>,
+    //False until the fee has been read from the factory and set on all of the pools of the dex
+    fee_synced: bool,
+}
+
+//Fee to set on the pools of a watched dex
+struct PoolFeeUpdate {
+    pools: HashSet<H160>,
+    fee: u32,
+}
+
+pub struct FeeMonitor<M: Middleware> {
+    middleware: Arc<M>,
+    watched_dexes: Vec<WatchedDex>,
+    //Pools that have already been matched against the watched dexes, so the factories are only asked about new pools
+    checked_pools: HashSet<H160>,
+    request_throttle: Mutex<RequestThrottle>,
+    sender: broadcast::Sender<FeeChanged>,
+}
+
+impl<M: Middleware> FeeMonitor<M> {
+    pub fn new(middleware: Arc<M>, channel_capacity: usize) -> FeeMonitor<M> {
+        let (sender, _) = broadcast::channel(channel_capacity);
+
+        FeeMonitor {
+            middleware,
+            watched_dexes: vec![],
+            checked_pools: HashSet::new(),
+            request_throttle: Mutex::new(RequestThrottle::new(0)),
+            sender,
+        }
+    }
+
+    //Throttles the factory requests and retries rate limited requests according to the throttle's retry policy
+    pub fn with_request_throttle(mut self, request_throttle: RequestThrottle) -> FeeMonitor<M> {
+        self.request_throttle = Mutex::new(request_throttle);
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FeeChanged> {
+        self.sender.subscribe()
+    }
+
+    //Registers the dex, returning false if the dex has an immutable fee and was not registered.
+    //On the next poll, the pools in the registry are matched to the dex by asking its factory for the pair of each pool's tokens,
+    //and the current factory fee is set on every matched pool.
+    pub fn watch(&mut self, dex: Dex) -> bool {
+        match dex {
+            Dex::UniswapV2(uniswap_v2_dex) if uniswap_v2_dex.has_mutable_fee() => {
+                self.watched_dexes.push(WatchedDex {
+                    dex: uniswap_v2_dex,
+                    pools: HashSet::new(),
+                    pending_pools: HashSet::new(),
+                    fee_synced: false,
+                });
+
+                //Pools that were checked before this dex was watched need to be checked against it as well,
+                //unless they already belong to another dex
+                self.checked_pools = self
+                    .watched_dexes
+                    .iter()
+                    .flat_map(|watched_dex| watched_dex.pools.iter().copied())
+                    .collect();
+
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.watched_dexes.is_empty()
+    }
+
+    //Matches the new pools in the registry against the watched dexes, then gets the current fee for each watched dex.
+    //The fee is set on newly matched pools, and on all pools of a dex the first time it is polled or when its fee changes.
+    //Fee changes are broadcast and returned.
+    pub async fn poll(&mut self, pools: &mut [Pool]) -> Result<Vec<FeeChanged>, CFMMError<M>> {
+        let new_pools = self.new_pools(pools);
+        self.match_pools(new_pools).await?;
+
+        let (fee_changes, fee_updates) = self.get_fee_updates().await?;
+        self.apply_fee_updates(pools, &fee_changes, &fee_updates);
+
+        Ok(fee_changes)
+    }
+
+    //Polls the factories on an interval until an error is encountered. Returns immediately if no dexes are watched.
+    //The registry is only locked to read the new pools and to apply fee updates, never while waiting on the middleware.
+    pub async fn run(
+        mut self,
+        pools: Arc<RwLock<Vec<Pool>>>,
+        interval: Duration,
+    ) -> Result<(), CFMMError<M>> {
+        if self.is_idle() {
+            return Ok(());
+        }
+
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            let new_pools = self.new_pools(&pools.read().await);
+            self.match_pools(new_pools).await?;
+
+            let (fee_changes, fee_updates) = self.get_fee_updates().await?;
+            if !fee_changes.is_empty() || !fee_updates.is_empty() {
+                self.apply_fee_updates(&mut pools.write().await, &fee_changes, &fee_updates);
+            }
+        }
+    }
+
+    //Returns the address and tokens of the populated Uniswap V2 pools that have not been checked yet
+    fn new_pools(&self, pools: &[Pool]) -> Vec<(H160, H160, H160)> {
+        pools
+            .iter()
+            .filter_map(|pool| match pool {
+                Pool::UniswapV2(uniswap_v2_pool)
+                    if !uniswap_v2_pool.token_a.is_zero()
+                        && !self.checked_pools.contains(&uniswap_v2_pool.address) =>
+                {
+                    Some((
+                        uniswap_v2_pool.address,
+                        uniswap_v2_pool.token_a,
+                        uniswap_v2_pool.token_b,
+                    ))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    //Adds each pool to the first watched dex whose factory returns it as the pair for its tokens.
+    //The pair lookups are batched via multicall, throttled and retried when rate limited.
+    //Multicall3 is deployed at the same address on all supported chains, so the chain id is not requested.
+    async fn match_pools(
+        &mut self,
+        new_pools: Vec<(H160, H160, H160)>,
+    ) -> Result<(), CFMMError<M>> {
+        if new_pools.is_empty() || self.watched_dexes.is_empty() {
+            return Ok(());
+        }
+
+        let retry_policy = self
+            .request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
+
+        let mut multicall = Multicall::new_with_chain_id(
+            self.middleware.clone(),
+            Some(MULTICALL_ADDRESS),
+            None::<u64>,
+        )?;
+
+        let pools_per_batch = (LOOKUP_BATCH_SIZE / self.watched_dexes.len()).max(1);
+        for new_pools in new_pools.chunks(pools_per_batch) {
+            multicall.clear_calls();
+            for (_, token_a, token_b) in new_pools {
+                for watched_dex in self.watched_dexes.iter() {
+                    multicall.add_call(
+                        abi::IUniswapV2Factory::new(
+                            watched_dex.dex.factory_address,
+                            self.middleware.clone(),
+                        )
+                        .get_pair(*token_a, *token_b),
+                        true,
+                    );
+                }
+            }
+
+            let multicall = &multicall;
+            let request_throttle = &self.request_throttle;
+            let results = retry_policy
+                .retry(|| async move {
+                    increment_or_sleep(request_throttle, 1).await;
+                    Ok(multicall.call_raw().await?)
+                })
+                .await?;
+
+            //Results are ordered by pool, then by watched dex. Lookups that fail are treated as not matching the dex.
+            for ((address, _, _), results) in new_pools
+                .iter()
+                .zip(results.chunks(self.watched_dexes.len()))
+            {
+                let matched_dex = results.iter().position(|result| {
+                    matches!(result, Ok(token) if token.clone().into_address() == Some(*address))
+                });
+
+                if let Some(matched_dex) = matched_dex {
+                    let watched_dex = &mut self.watched_dexes[matched_dex];
+                    watched_dex.pools.insert(*address);
+                    watched_dex.pending_pools.insert(*address);
+                }
+
+                self.checked_pools.insert(*address);
+            }
+        }
+
+        Ok(())
+    }
+
+    //Gets the fee of every watched dex before updating any of them, so that a failed request leaves the monitor unchanged.
+    //Returns the fee changes along with the pools that need their fee set.
+    async fn get_fee_updates(
+        &mut self,
+    ) -> Result<(Vec<FeeChanged>, Vec<PoolFeeUpdate>), CFMMError<M>> {
+        let retry_policy = self
+            .request_throttle
+            .lock()
+            .expect("Error when acquiring request throttle mutex lock")
+            .retry_policy();
+
+        let mut new_fees = vec![];
+        for watched_dex in self.watched_dexes.iter() {
+            let request_throttle = &self.request_throttle;
+            let middleware = &self.middleware;
+            let dex = &watched_dex.dex;
+
+            new_fees.push(
+                retry_policy
+                    .retry(|| async move {
+                        increment_or_sleep(request_throttle, 1).await;
+                        get_factory_fee(dex, middleware.clone()).await
+                    })
+                    .await?,
+            );
+        }
+
+        let mut fee_changes = vec![];
+        let mut fee_updates = vec![];
+        for (watched_dex, new_fee) in self.watched_dexes.iter_mut().zip(new_fees) {
+            let old_fee = watched_dex.dex.fee as u32;
+            let pending_pools = std::mem::take(&mut watched_dex.pending_pools);
+
+            if new_fee != old_fee {
+                watched_dex.dex.fee = new_fee as u64;
+
+                fee_changes.push(FeeChanged {
+                    dex: watched_dex.dex.factory_address,
+                    old: old_fee,
+                    new: new_fee,
+                });
+            }
+
+            //Pools are created with a default fee, so every pool gets the factory fee the first time the dex is polled
+            let pools = if new_fee != old_fee || !watched_dex.fee_synced {
+                watched_dex.pools.clone()
+            } else {
+                pending_pools
+            };
+            watched_dex.fee_synced = true;
+
+            if !pools.is_empty() {
+                fee_updates.push(PoolFeeUpdate {
+                    pools,
+                    fee: new_fee,
+                });
+            }
+        }
+
+        Ok((fee_changes, fee_updates))
+    }
+
+    //Sets the fee on the updated pools and broadcasts the fee changes
+    fn apply_fee_updates(
+        &self,
+        pools: &mut [Pool],
+        fee_changes: &[FeeChanged],
+        fee_updates: &[PoolFeeUpdate],
+    ) {
+        for fee_update in fee_updates {
+            apply_fee_change(pools, &fee_update.pools, fee_update.fee);
+        }
+
+        for fee_changed in fee_changes {
+            //Sending only fails when there are no receivers, which is fine
+            let _ = self.sender.send(*fee_changed);
+        }
+    }
+}
+
+//Calls the fee getter on the factory and returns the fee in pool fee units.
+//Returns an error unless the getter returns exactly one uint256 that is at most `POOL_FEE_DENOMINATOR` once scaled.
+pub async fn get_factory_fee<M: Middleware>(
+    dex: &UniswapV2Dex,
+    middleware: Arc<M>,
+) -> Result<u32, CFMMError<M>> {
+    match dex.fee_source {
+        FeeSource::Immutable => Ok(dex.fee as u32),
+
+        FeeSource::FactoryGetter {
+            selector,
+            fee_denominator,
+        } => {
+            let return_data = middleware
+                .call(
+                    &TransactionRequest::new()
+                        .to(dex.factory_address)
+                        .data(Bytes::from(selector.to_vec()))
+                        .into(),
+                    None,
+                )
+                .await
+                .map_err(CFMMError::MiddlewareError)?;
+
+            //The decoder ignores trailing data, so the length is checked first
+            if return_data.len() != 32 {
+                return Err(CFMMError::InvalidFactoryFee(dex.factory_address));
+            }
+
+            let fee = ethers::abi::decode(&[ParamType::Uint(256)], &return_data)?
+                .pop()
+                .and_then(|token| token.into_uint())
+                .ok_or(CFMMError::InvalidFactoryFee(dex.factory_address))?;
+
+            let fee = scale_fee(fee, fee_denominator);
+            if fee as u64 > POOL_FEE_DENOMINATOR {
+                return Err(CFMMError::InvalidFactoryFee(dex.factory_address));
+            }
+
+            Ok(fee)
+        }
+    }
+}
+
+//Converts a fee with the given denominator into pool fee units
+pub fn scale_fee(fee: U256, fee_denominator: u64) -> u32 {
+    if fee_denominator == 0 {
+        return 0;
+    }
+
+    let fee = fee.saturating_mul(U256::from(POOL_FEE_DENOMINATOR)) / fee_denominator;
+
+    fee.min(U256::from(u32::MAX)).as_u32()
+}
+
+fn apply_fee_change(pools: &mut [Pool], affected_pools: &HashSet<H160>, new_fee: u32) {
+    for pool in pools.iter_mut() {
+        if let Pool::UniswapV2(uniswap_v2_pool) = pool {
+            if affected_pools.contains(&uniswap_v2_pool.address) {
+                uniswap_v2_pool.fee = new_fee;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc, time::Duration};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::{Bytes, H160, U256},
+    };
+    use tokio::sync::RwLock;
+
+    use super::{apply_fee_change, get_factory_fee, scale_fee, FeeChanged, FeeMonitor};
+    use crate::{
+        abi,
+        dex::{uniswap_v2::FeeSource, Dex, DexVariant},
+        errors::CFMMError,
+        mock_middleware::MockMiddleware,
+        pool::{Pool, UniswapV2Pool},
+    };
+
+    const FEE_SELECTOR: [u8; 4] = [0xdd, 0xca, 0x3f, 0x43];
+
+    fn mutable_fee_dex(factory: H160) -> Dex {
+        match Dex::new(factory, DexVariant::UniswapV2, 0, None) {
+            Dex::UniswapV2(uniswap_v2_dex) => {
+                Dex::UniswapV2(uniswap_v2_dex.with_fee_source(FeeSource::FactoryGetter {
+                    selector: FEE_SELECTOR,
+                    fee_denominator: 100000,
+                }))
+            }
+            Dex::UniswapV3(_) => unreachable!(),
+        }
+    }
+
+    fn populated_pool(address: H160, token_a: H160, token_b: H160) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address,
+            token_a,
+            token_b,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    fn uint(value: u64) -> Bytes {
+        ethers::abi::encode(&[Token::Uint(U256::from(value))]).into()
+    }
+
+    fn get_pair_calldata(factory: H160, token_a: H160, token_b: H160) -> Bytes {
+        abi::IUniswapV2Factory::new(factory, Arc::new(MockMiddleware::new(0)))
+            .get_pair(token_a, token_b)
+            .calldata()
+            .unwrap()
+    }
+
+    fn pool(address: H160) -> Pool {
+        Pool::UniswapV2(UniswapV2Pool {
+            address,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_immutable_fee_is_not_watched() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+        let mut fee_monitor = FeeMonitor::new(middleware, 16);
+
+        let dex = Dex::new(H160::zero(), DexVariant::UniswapV2, 0, None);
+        let mut pools = vec![pool(H160::from_low_u64_be(1))];
+
+        assert!(!fee_monitor.watch(dex));
+        assert!(fee_monitor.is_idle());
+
+        //No dexes are watched so the provider is never called
+        assert!(fee_monitor.poll(&mut pools).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mutable_fee_is_watched() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+        let mut fee_monitor = FeeMonitor::new(middleware, 16);
+
+        let dex = mutable_fee_dex(H160::zero());
+
+        assert!(fee_monitor.watch(dex));
+        assert!(!fee_monitor.is_idle());
+    }
+
+    #[test]
+    fn test_apply_fee_change() {
+        let mut pools = vec![
+            pool(H160::from_low_u64_be(1)),
+            pool(H160::from_low_u64_be(2)),
+        ];
+        let affected_pools = HashSet::from([H160::from_low_u64_be(2)]);
+
+        apply_fee_change(&mut pools, &affected_pools, 200);
+
+        assert_eq!(pools[0].fee(), 300);
+        assert_eq!(pools[1].fee(), 200);
+    }
+
+    #[test]
+    fn test_scale_fee() {
+        //0.25% expressed in basis points
+        assert_eq!(scale_fee(U256::from(25), 10000), 250);
+        //0.3% expressed with a denominator of 1000
+        assert_eq!(scale_fee(U256::from(3), 1000), 300);
+        assert_eq!(scale_fee(U256::from(3), 0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_factory_fee() {
+        let factory = H160::from_low_u64_be(1);
+        let dex = match mutable_fee_dex(factory) {
+            Dex::UniswapV2(uniswap_v2_dex) => uniswap_v2_dex,
+            Dex::UniswapV3(_) => unreachable!(),
+        };
+
+        let factory_fee = |return_data: Bytes| {
+            let middleware = Arc::new(MockMiddleware::new(0).with_response(
+                factory,
+                FEE_SELECTOR.to_vec().into(),
+                return_data,
+            ));
+            get_factory_fee(&dex, middleware)
+        };
+
+        assert_eq!(factory_fee(uint(250)).await.unwrap(), 250);
+
+        //Empty data, trailing data and fees above 100% are rejected
+        let mut trailing_data = uint(250).to_vec();
+        trailing_data.extend(uint(1).to_vec());
+
+        for return_data in [Bytes::new(), trailing_data.into(), uint(100001)] {
+            assert!(matches!(
+                factory_fee(return_data).await,
+                Err(CFMMError::InvalidFactoryFee(address)) if address == factory
+            ));
+        }
+    }
+
+    fn pair(address: u64) -> Bytes {
+        ethers::abi::encode(&[Token::Address(H160::from_low_u64_be(address))]).into()
+    }
+
+    #[tokio::test]
+    async fn test_poll_matches_new_pools() {
+        let factory = H160::from_low_u64_be(1);
+        let (token_a, token_b, token_c, token_d) = (
+            H160::from_low_u64_be(10),
+            H160::from_low_u64_be(11),
+            H160::from_low_u64_be(12),
+            H160::from_low_u64_be(13),
+        );
+
+        //Pool 3 is from another dex, so the factory returns a different pair for its tokens
+        let middleware = Arc::new(
+            MockMiddleware::new(0)
+                .with_response(factory, FEE_SELECTOR.to_vec().into(), uint(250))
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_d),
+                    pair(1),
+                )
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_b),
+                    pair(2),
+                )
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_c),
+                    pair(4),
+                ),
+        );
+
+        let mut pools = vec![populated_pool(H160::from_low_u64_be(1), token_a, token_d)];
+
+        let mut fee_monitor = FeeMonitor::new(middleware.clone(), 16);
+        assert!(fee_monitor.watch(mutable_fee_dex(factory)));
+
+        //Pools 2 and 3 are added to the registry after the dex is watched
+        pools.push(populated_pool(H160::from_low_u64_be(2), token_a, token_b));
+        pools.push(populated_pool(H160::from_low_u64_be(3), token_a, token_c));
+
+        let fee_changes = fee_monitor.poll(&mut pools).await.unwrap();
+
+        assert_eq!(
+            fee_changes,
+            vec![FeeChanged {
+                dex: factory,
+                old: 300,
+                new: 250,
+            }]
+        );
+        assert_eq!(pools[0].fee(), 250);
+        assert_eq!(pools[1].fee(), 250);
+        assert_eq!(pools[2].fee(), 300);
+
+        //One multicall for the pair lookups and the fee
+        assert_eq!(middleware.requests(), 2);
+
+        //Checked pools are not looked up again
+        assert!(fee_monitor.poll(&mut pools).await.unwrap().is_empty());
+        assert_eq!(middleware.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_poll_sets_factory_fee_on_matched_pools() {
+        let factory = H160::from_low_u64_be(1);
+        let (token_a, token_b, token_c) = (
+            H160::from_low_u64_be(10),
+            H160::from_low_u64_be(11),
+            H160::from_low_u64_be(12),
+        );
+
+        let middleware = Arc::new(
+            MockMiddleware::new(0)
+                .with_response(factory, FEE_SELECTOR.to_vec().into(), uint(250))
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_b),
+                    pair(1),
+                )
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_c),
+                    pair(2),
+                ),
+        );
+
+        //The configured fee already matches the factory fee, but the pools were created with the default fee
+        let dex = match mutable_fee_dex(factory) {
+            Dex::UniswapV2(mut uniswap_v2_dex) => {
+                uniswap_v2_dex.fee = 250;
+                Dex::UniswapV2(uniswap_v2_dex)
+            }
+            Dex::UniswapV3(_) => unreachable!(),
+        };
+
+        let mut pools = vec![populated_pool(H160::from_low_u64_be(1), token_a, token_b)];
+
+        let mut fee_monitor = FeeMonitor::new(middleware, 16);
+        assert!(fee_monitor.watch(dex));
+
+        //The first poll reconciles the pools with the factory fee without a fee change
+        assert!(fee_monitor.poll(&mut pools).await.unwrap().is_empty());
+        assert_eq!(pools[0].fee(), 250);
+
+        //Pools matched on later polls get the current factory fee
+        pools.push(populated_pool(H160::from_low_u64_be(2), token_a, token_c));
+        assert!(fee_monitor.poll(&mut pools).await.unwrap().is_empty());
+        assert_eq!(pools[1].fee(), 250);
+    }
+
+    #[tokio::test]
+    async fn test_run_applies_fee_changes() {
+        let factory = H160::from_low_u64_be(1);
+        let (token_a, token_b) = (H160::from_low_u64_be(10), H160::from_low_u64_be(11));
+        let middleware = Arc::new(
+            MockMiddleware::new(0)
+                .with_response(factory, FEE_SELECTOR.to_vec().into(), uint(250))
+                .with_response(
+                    factory,
+                    get_pair_calldata(factory, token_a, token_b),
+                    pair(1),
+                ),
+        );
+
+        let pools = Arc::new(RwLock::new(vec![populated_pool(
+            H160::from_low_u64_be(1),
+            token_a,
+            token_b,
+        )]));
+
+        let mut fee_monitor = FeeMonitor::new(middleware, 16);
+        assert!(fee_monitor.watch(mutable_fee_dex(factory)));
+        let mut receiver = fee_monitor.subscribe();
+
+        let handle = tokio::spawn(fee_monitor.run(pools.clone(), Duration::from_millis(10)));
+
+        let fee_changed = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(fee_changed.new, 250);
+        assert_eq!(pools.read().await[0].fee(), 250);
+
+        handle.abort();
+    }
+}
@@ -0,0 +1,479 @@
+//Utilities for testing against a local anvil fork of mainnet, enabled with the `fork-test-utils` feature.
+//Requires anvil to be installed and an archive node endpoint to fork from, ex. `ETHEREUM_MAINNET_ENDPOINT`.
+use std::{str::FromStr, sync::Arc};
+
+use ethers::{
+    prelude::abigen,
+    providers::{Http, Middleware, Provider, ProviderError},
+    types::{Address, TransactionRequest, H160, U256},
+    utils::{Anvil, AnvilInstance},
+};
+
+//Block that the reference tests fork from, so that pool state is reproducible
+pub const DEFAULT_FORK_BLOCK: u64 = 17000000;
+pub const FORK_URL_ENV_VAR: &str = "ETHEREUM_MAINNET_ENDPOINT";
+
+pub const UNISWAP_V2_ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
+pub const UNISWAP_V3_QUOTER: &str = "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6";
+
+abigen!(
+    IUniswapV2Router,
+    r#"[
+        function getAmountsOut(uint256 amountIn, address[] memory path) external view returns (uint256[] memory amounts)
+    ]"#;
+
+    IQuoter,
+    r#"[
+        function quoteExactInputSingle(address tokenIn, address tokenOut, uint24 fee, uint256 amountIn, uint160 sqrtPriceLimitX96) external returns (uint256 amountOut)
+    ]"#;
+
+    IERC20,
+    r#"[
+        function balanceOf(address account) external view returns (uint256)
+        function transfer(address to, uint256 amount) external returns (bool)
+    ]"#;
+);
+
+//An anvil instance forked at a pinned block. The anvil process is killed when this is dropped.
+pub struct ForkedChain {
+    anvil: AnvilInstance,
+    fork_block: u64,
+    middleware: Arc<Provider<Http>>,
+}
+
+impl ForkedChain {
+    pub fn spawn(fork_url: &str, fork_block: u64) -> ForkedChain {
+        let anvil = Anvil::new()
+            .fork(fork_url)
+            .fork_block_number(fork_block)
+            .spawn();
+
+        let middleware = Arc::new(
+            Provider::<Http>::try_from(anvil.endpoint())
+                .expect("Could not connect to anvil instance"),
+        );
+
+        ForkedChain {
+            anvil,
+            fork_block,
+            middleware,
+        }
+    }
+
+    //Forks from the endpoint in `ETHEREUM_MAINNET_ENDPOINT`
+    pub fn spawn_from_env(fork_block: u64) -> ForkedChain {
+        let fork_url = std::env::var(FORK_URL_ENV_VAR)
+            .unwrap_or_else(|_| panic!("Could not get {}", FORK_URL_ENV_VAR));
+
+        ForkedChain::spawn(&fork_url, fork_block)
+    }
+
+    pub fn middleware(&self) -> Arc<Provider<Http>> {
+        self.middleware.clone()
+    }
+
+    pub fn fork_block(&self) -> u64 {
+        self.fork_block
+    }
+
+    pub fn endpoint(&self) -> String {
+        self.anvil.endpoint()
+    }
+
+    //Unlocked accounts funded by anvil
+    pub fn accounts(&self) -> &[Address] {
+        self.anvil.addresses()
+    }
+
+    pub async fn set_balance(&self, account: H160, balance: U256) -> Result<(), ProviderError> {
+        self.middleware
+            .request("anvil_setBalance", (account, balance))
+            .await
+    }
+
+    pub async fn impersonate_account(&self, account: H160) -> Result<(), ProviderError> {
+        self.middleware
+            .request("anvil_impersonateAccount", [account])
+            .await
+    }
+
+    pub async fn stop_impersonating_account(&self, account: H160) -> Result<(), ProviderError> {
+        self.middleware
+            .request("anvil_stopImpersonatingAccount", [account])
+            .await
+    }
+
+    //Transfers `amount` of `token` from `holder` to `account` by impersonating the holder
+    pub async fn fund_token(
+        &self,
+        token: H160,
+        holder: H160,
+        account: H160,
+        amount: U256,
+    ) -> Result<(), ProviderError> {
+        self.impersonate_account(holder).await?;
+        //Make sure the holder can pay for gas
+        self.set_balance(holder, U256::exp10(18)).await?;
+
+        let calldata = IERC20::new(token, self.middleware.clone())
+            .transfer(account, amount)
+            .calldata()
+            .expect("Could not encode transfer calldata");
+
+        let tx = TransactionRequest::new()
+            .from(holder)
+            .to(token)
+            .data(calldata);
+
+        self.middleware
+            .send_transaction(tx, None)
+            .await?
+            .await?
+            .ok_or_else(|| ProviderError::CustomError("Transfer was not mined".to_string()))?;
+
+        self.stop_impersonating_account(holder).await
+    }
+
+    //Takes a snapshot of the current chain state, returning the snapshot id
+    pub async fn snapshot(&self) -> Result<U256, ProviderError> {
+        self.middleware.request("evm_snapshot", ()).await
+    }
+
+    //Reverts the chain state to the snapshot. Snapshots can only be reverted to once.
+    pub async fn revert(&self, snapshot_id: U256) -> Result<bool, ProviderError> {
+        self.middleware.request("evm_revert", [snapshot_id]).await
+    }
+
+    pub async fn mine(&self, blocks: u64) -> Result<(), ProviderError> {
+        self.middleware
+            .request("anvil_mine", [U256::from(blocks)])
+            .await
+    }
+
+    //Gets the amount out from the Uniswap V2 router for a single hop swap
+    pub async fn get_v2_amount_out(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        amount_in: U256,
+    ) -> Result<U256, ProviderError> {
+        let router = IUniswapV2Router::new(
+            H160::from_str(UNISWAP_V2_ROUTER).unwrap(),
+            self.middleware.clone(),
+        );
+
+        let amounts = router
+            .get_amounts_out(amount_in, vec![token_in, token_out])
+            .call()
+            .await
+            .map_err(|err| ProviderError::CustomError(err.to_string()))?;
+
+        Ok(amounts[1])
+    }
+
+    //Gets the amount out from the Uniswap V3 quoter for a single pool swap
+    pub async fn quote_exact_input_single(
+        &self,
+        token_in: H160,
+        token_out: H160,
+        fee: u32,
+        amount_in: U256,
+    ) -> Result<U256, ProviderError> {
+        let quoter = IQuoter::new(
+            H160::from_str(UNISWAP_V3_QUOTER).unwrap(),
+            self.middleware.clone(),
+        );
+
+        quoter
+            .quote_exact_input_single(token_in, token_out, fee, amount_in, U256::zero())
+            .call()
+            .await
+            .map_err(|err| ProviderError::CustomError(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ethers::{
+        providers::Middleware,
+        types::{BlockNumber, H160, U256},
+    };
+
+    use super::{ForkedChain, DEFAULT_FORK_BLOCK, IERC20};
+    use crate::{
+        checkpoint,
+        dex::{Dex, DexVariant},
+        pool::{Pool, UniswapV3Pool},
+        progress::ProgressMode,
+        sync,
+        throttle::RequestThrottle,
+    };
+
+    const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+    const USDC_WETH_V2: &str = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc";
+    const USDC_WETH_V3: &str = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640";
+
+    fn h160(address: &str) -> H160 {
+        H160::from_str(address).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_revert() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        let account = fork.accounts()[0];
+        let usdc = IERC20::new(h160(USDC), middleware.clone());
+
+        let snapshot_id = fork.snapshot().await.unwrap();
+
+        //Fund the account from the USDC/WETH V2 pair
+        fork.fund_token(h160(USDC), h160(USDC_WETH_V2), account, U256::from(1000000))
+            .await
+            .unwrap();
+        assert_eq!(
+            usdc.balance_of(account).call().await.unwrap(),
+            U256::from(1000000)
+        );
+
+        assert!(fork.revert(snapshot_id).await.unwrap());
+        assert_eq!(usdc.balance_of(account).call().await.unwrap(), U256::zero());
+    }
+
+    #[tokio::test]
+    async fn test_sync_dex_block_range() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        let dex = Dex::new(
+            h160("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+            DexVariant::UniswapV3,
+            12369621,
+            None,
+        );
+
+        let from_block = DEFAULT_FORK_BLOCK - 10000;
+        let mut pools = dex
+            .get_all_pools_from_logs_within_range(
+                BlockNumber::Number(from_block.into()),
+                BlockNumber::Number(DEFAULT_FORK_BLOCK.into()),
+                2000,
+                std::sync::Arc::new(std::sync::Mutex::new(RequestThrottle::new(0))),
+                indicatif::ProgressBar::hidden(),
+                middleware.clone(),
+            )
+            .await
+            .unwrap();
+
+        assert!(!pools.is_empty());
+
+        dex.get_all_pool_data(
+            &mut pools,
+            std::sync::Arc::new(std::sync::Mutex::new(RequestThrottle::new(0))),
+            indicatif::ProgressBar::hidden(),
+            middleware,
+        )
+        .await
+        .unwrap();
+
+        assert!(pools.iter().all(|pool| pool.address() != H160::zero()));
+    }
+
+    #[tokio::test]
+    async fn test_populate_pools() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+
+        let pools = sync::sync_pools_from_addresses(
+            vec![
+                (h160(USDC_WETH_V2), DexVariant::UniswapV2),
+                (h160(USDC_WETH_V3), DexVariant::UniswapV3),
+            ],
+            fork.middleware(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(pools.len(), 2);
+        assert!(pools
+            .iter()
+            .all(|pool| pool.last_synced_block() == DEFAULT_FORK_BLOCK));
+    }
+
+    #[tokio::test]
+    async fn test_v2_quote_matches_router() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        let pool = Pool::new_from_address(h160(USDC_WETH_V2), DexVariant::UniswapV2, middleware)
+            .await
+            .unwrap();
+
+        let amount_in = U256::from(1000000000);
+        let amount_out = pool
+            .simulate_swap(h160(USDC), amount_in, fork.middleware())
+            .await
+            .unwrap();
+
+        let expected_amount_out = fork
+            .get_v2_amount_out(h160(USDC), h160(WETH), amount_in)
+            .await
+            .unwrap();
+
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
+    #[tokio::test]
+    async fn test_v3_quote_matches_quoter() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        let pool = Pool::new_from_address(h160(USDC_WETH_V3), DexVariant::UniswapV3, middleware)
+            .await
+            .unwrap();
+
+        let amount_in = U256::from(1000000000);
+        let amount_out = pool
+            .simulate_swap(h160(USDC), amount_in, fork.middleware())
+            .await
+            .unwrap();
+
+        let expected_amount_out = fork
+            .quote_exact_input_single(h160(USDC), h160(WETH), pool.fee(), amount_in)
+            .await
+            .unwrap();
+
+        assert_eq!(amount_out, expected_amount_out);
+    }
+
+    #[tokio::test]
+    async fn test_v3_local_simulation_matches_quoter() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        //USDC/WETH 0.05%, USDC/WETH 0.3% and WBTC/WETH 0.3%
+        let pools = [
+            (USDC_WETH_V3, U256::from(1000000000)),
+            (
+                "0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8",
+                U256::from(100000000000_u64),
+            ),
+            (
+                "0xCBCdF9626bC03E24f779434178A73a0B4bad62eD",
+                U256::from(10000000),
+            ),
+        ];
+
+        for (address, amount_in) in pools {
+            let mut pool = UniswapV3Pool::new_from_address(h160(address), middleware.clone())
+                .await
+                .unwrap();
+            pool.sync_ticks(150, None, middleware.clone())
+                .await
+                .unwrap();
+
+            let amount_out = pool.simulate_swap(pool.token_a, amount_in).unwrap();
+
+            let expected_amount_out = fork
+                .quote_exact_input_single(pool.token_a, pool.token_b, pool.fee, amount_in)
+                .await
+                .unwrap();
+
+            assert_eq!(amount_out, expected_amount_out);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v3_local_simulation_one_for_zero_matches_quoter() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        //WETH in for USDC/WETH 0.05%, USDC/WETH 0.3% and WBTC/WETH 0.3%, walking the tick window upwards
+        let pools = [
+            USDC_WETH_V3,
+            "0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8",
+            "0xCBCdF9626bC03E24f779434178A73a0B4bad62eD",
+        ];
+
+        for address in pools {
+            let mut pool = UniswapV3Pool::new_from_address(h160(address), middleware.clone())
+                .await
+                .unwrap();
+            pool.sync_ticks(150, None, middleware.clone())
+                .await
+                .unwrap();
+
+            let amount_in = U256::exp10(18);
+            let amount_out = pool.simulate_swap(pool.token_b, amount_in).unwrap();
+
+            let expected_amount_out = fork
+                .quote_exact_input_single(pool.token_b, pool.token_a, pool.fee, amount_in)
+                .await
+                .unwrap();
+
+            assert_eq!(amount_out, expected_amount_out);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_round_trip() {
+        let fork = ForkedChain::spawn_from_env(DEFAULT_FORK_BLOCK);
+        let middleware = fork.middleware();
+
+        let dexes = vec![
+            Dex::new(
+                h160("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+                DexVariant::UniswapV2,
+                10000835,
+                Some(300),
+            ),
+            Dex::new(
+                h160("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+                DexVariant::UniswapV3,
+                12369621,
+                None,
+            ),
+        ];
+
+        let pools = sync::sync_pools_from_addresses(
+            vec![
+                (h160(USDC_WETH_V2), DexVariant::UniswapV2),
+                (h160(USDC_WETH_V3), DexVariant::UniswapV3),
+            ],
+            middleware.clone(),
+        )
+        .await
+        .unwrap();
+
+        let current_block = middleware.get_block_number().await.unwrap().as_u64();
+        let checkpoint_path = std::env::temp_dir().join("cfmms_fork_checkpoint.json");
+        let checkpoint_path = checkpoint_path.to_str().unwrap();
+
+        checkpoint::construct_checkpoint(dexes, &pools, current_block, checkpoint_path).unwrap();
+
+        let config = checkpoint::CheckpointSyncConfig {
+            progress_config: ProgressMode::Quiet.into(),
+            ..Default::default()
+        };
+
+        let (_, synced_pools, _) =
+            checkpoint::sync_pools_from_checkpoint_with_config(checkpoint_path, config, middleware)
+                .await
+                .unwrap();
+
+        std::fs::remove_file(checkpoint_path).unwrap();
+
+        assert_eq!(synced_pools.len(), pools.len());
+        for pool in pools {
+            let synced_pool = synced_pools
+                .iter()
+                .find(|synced_pool| synced_pool.address() == pool.address())
+                .unwrap();
+
+            //The chain state has not changed since the checkpoint so the pool state should be identical
+            assert_eq!(*synced_pool, pool);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use ethers::types::{H160, U256};
+use serde::{Deserialize, Serialize};
+use uniswap_v3_math::sqrt_price_math::Q96;
+
+use crate::{
+    math,
+    pool::{Pool, UniswapV2Pool, UniswapV3Pool},
+};
+
+//Weights and normalization bounds used when scoring a pool. Each component score is normalized to [0, 1]
+//and the final score is the weighted average of the components.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HealthWeights {
+    pub staleness: f64,
+    pub depth: f64,
+    pub anomalies: f64,
+    pub token_risk: f64,
+    //Number of blocks behind the head at which the staleness score reaches 0
+    pub max_age_blocks: u64,
+    //Depth of the quote token (in whole units) at which the depth score saturates at 1
+    pub target_depth: f64,
+    //Number of anomalies and failed syncs at which the anomaly score reaches 0
+    pub max_anomalies: u32,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        HealthWeights {
+            staleness: 1.0,
+            depth: 1.0,
+            anomalies: 1.0,
+            token_risk: 1.0,
+            max_age_blocks: 50,
+            target_depth: 100.0,
+            max_anomalies: 10,
+        }
+    }
+}
+
+//Observed state for a pool that is not stored on the pool itself
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub quote_token: H160,
+    pub current_block: u64,
+    pub anomalies: u32,
+    pub failed_syncs: u32,
+    pub fee_on_transfer: bool,
+    pub rebasing: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PoolHealth {
+    pub address: H160,
+    pub staleness: f64,
+    pub depth: f64,
+    pub anomalies: f64,
+    pub token_risk: f64,
+    pub score: f64,
+}
+
+//Returns the weighted health score of the pool in the range [0, 1]
+pub fn score_pool(pool: &Pool, stats: &PoolStats, weights: &HealthWeights) -> f64 {
+    pool_health(pool, stats, weights).score
+}
+
+//Computes each component of the pool health as well as the weighted score
+pub fn pool_health(pool: &Pool, stats: &PoolStats, weights: &HealthWeights) -> PoolHealth {
+    let age = stats.current_block.saturating_sub(pool.last_synced_block());
+    let staleness = if weights.max_age_blocks == 0 {
+        if age == 0 {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        1.0 - (age as f64 / weights.max_age_blocks as f64).min(1.0)
+    };
+
+    let depth = if weights.target_depth > 0.0 {
+        (quote_token_depth(pool, stats.quote_token) / weights.target_depth).min(1.0)
+    } else {
+        1.0
+    };
+
+    let anomaly_count = stats.anomalies.saturating_add(stats.failed_syncs);
+    let anomalies = if weights.max_anomalies == 0 {
+        if anomaly_count == 0 {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        1.0 - (anomaly_count as f64 / weights.max_anomalies as f64).min(1.0)
+    };
+
+    let token_risk = if stats.fee_on_transfer || stats.rebasing {
+        0.0
+    } else {
+        1.0
+    };
+
+    let total_weight = weights.staleness + weights.depth + weights.anomalies + weights.token_risk;
+    let score = if total_weight > 0.0 {
+        (staleness * weights.staleness
+            + depth * weights.depth
+            + anomalies * weights.anomalies
+            + token_risk * weights.token_risk)
+            / total_weight
+    } else {
+        0.0
+    };
+
+    PoolHealth {
+        address: pool.address(),
+        staleness,
+        depth,
+        anomalies,
+        token_risk,
+        score,
+    }
+}
+
+//Excludes pools with a health score below `min_score` from quoting and routing
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthFilter {
+    pub stats: HashMap<H160, PoolStats>,
+    pub weights: HealthWeights,
+    pub min_score: f64,
+}
+
+impl HealthFilter {
+    pub fn new(
+        stats: HashMap<H160, PoolStats>,
+        weights: HealthWeights,
+        min_score: f64,
+    ) -> HealthFilter {
+        HealthFilter {
+            stats,
+            weights,
+            min_score,
+        }
+    }
+
+    //Returns true if the pool has stats and a health score at or above `min_score`
+    pub fn is_healthy(&self, pool: &Pool) -> bool {
+        is_healthy(pool, &self.stats, &self.weights, self.min_score)
+    }
+}
+
+//Returns the pools with a health score at or above `min_score`. Pools without stats are excluded.
+pub fn retain_healthy_pools(
+    pools: Vec<Pool>,
+    stats: &HashMap<H160, PoolStats>,
+    weights: &HealthWeights,
+    min_score: f64,
+) -> Vec<Pool> {
+    pools
+        .into_iter()
+        .filter(|pool| is_healthy(pool, stats, weights, min_score))
+        .collect()
+}
+
+fn is_healthy(
+    pool: &Pool,
+    stats: &HashMap<H160, PoolStats>,
+    weights: &HealthWeights,
+    min_score: f64,
+) -> bool {
+    stats
+        .get(&pool.address())
+        .map(|pool_stats| score_pool(pool, pool_stats, weights) >= min_score)
+        .unwrap_or(false)
+}
+
+//Amount of the quote token held by the pool, in whole units. Returns 0 if the quote token is not in the pool.
+pub fn quote_token_depth(pool: &Pool, quote_token: H160) -> f64 {
+    match pool {
+        Pool::UniswapV2(pool) => uniswap_v2_depth(pool, quote_token),
+        Pool::UniswapV3(pool) => uniswap_v3_depth(pool, quote_token),
+    }
+}
+
+fn uniswap_v2_depth(pool: &UniswapV2Pool, quote_token: H160) -> f64 {
+    if quote_token == pool.token_a {
+        u256_to_f64(U256::from(pool.reserve_0), pool.token_a_decimals)
+    } else if quote_token == pool.token_b {
+        u256_to_f64(U256::from(pool.reserve_1), pool.token_b_decimals)
+    } else {
+        0.0
+    }
+}
+
+//Virtual reserves at the current price, x = L / sqrt(P) and y = L * sqrt(P)
+fn uniswap_v3_depth(pool: &UniswapV3Pool, quote_token: H160) -> f64 {
+    if pool.sqrt_price.is_zero() {
+        return 0.0;
+    }
+
+    let liquidity = U256::from(pool.liquidity);
+
+    if quote_token == pool.token_a {
+        uniswap_v3_math::full_math::mul_div(liquidity, Q96, pool.sqrt_price)
+            .map(|reserve| u256_to_f64(reserve, pool.token_a_decimals))
+            .unwrap_or(0.0)
+    } else if quote_token == pool.token_b {
+        uniswap_v3_math::full_math::mul_div(liquidity, pool.sqrt_price, Q96)
+            .map(|reserve| u256_to_f64(reserve, pool.token_b_decimals))
+            .unwrap_or(0.0)
+    } else {
+        0.0
+    }
+}
+
+fn u256_to_f64(amount: U256, decimals: u8) -> f64 {
+    math::u256_to_f64(amount) / 10_f64.powi(decimals as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use ethers::types::H160;
+
+    use super::{pool_health, retain_healthy_pools, score_pool, HealthWeights, PoolStats};
+    use crate::{pool::Pool, test_utils::usdc_weth_pool};
+
+    #[test]
+    fn test_score_pool_healthy() {
+        let pool = Pool::UniswapV2(usdc_weth_pool(100));
+        let stats = PoolStats {
+            quote_token: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+            current_block: 100,
+            ..Default::default()
+        };
+
+        assert_eq!(score_pool(&pool, &stats, &HealthWeights::default()), 1.0);
+    }
+
+    #[test]
+    fn test_score_pool_components() {
+        let pool = Pool::UniswapV2(usdc_weth_pool(75));
+        let stats = PoolStats {
+            quote_token: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+            current_block: 100,
+            anomalies: 3,
+            failed_syncs: 2,
+            fee_on_transfer: true,
+            rebasing: false,
+        };
+
+        let health = pool_health(&pool, &stats, &HealthWeights::default());
+
+        assert_eq!(health.staleness, 0.5);
+        assert_eq!(health.depth, 1.0);
+        assert_eq!(health.anomalies, 0.5);
+        assert_eq!(health.token_risk, 0.0);
+        assert_eq!(health.score, 0.5);
+
+        //Scoring must be deterministic given the same inputs
+        assert_eq!(
+            health,
+            pool_health(&pool, &stats, &HealthWeights::default())
+        );
+    }
+
+    #[test]
+    fn test_retain_healthy_pools() {
+        let pool = Pool::UniswapV2(usdc_weth_pool(0));
+        let mut stats = HashMap::new();
+        stats.insert(
+            pool.address(),
+            PoolStats {
+                quote_token: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+                current_block: 1000,
+                ..Default::default()
+            },
+        );
+
+        let weights = HealthWeights::default();
+
+        assert_eq!(
+            retain_healthy_pools(vec![pool.clone()], &stats, &weights, 0.7).len(),
+            1
+        );
+        assert!(retain_healthy_pools(vec![pool.clone()], &stats, &weights, 0.8).is_empty());
+        assert!(retain_healthy_pools(vec![pool], &HashMap::new(), &weights, 0.0).is_empty());
+    }
+}
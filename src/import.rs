@@ -0,0 +1,577 @@
+use std::{
+    collections::HashSet,
+    io::BufRead,
+    sync::{Arc, Mutex},
+};
+
+use ethers::{
+    contract::Multicall,
+    providers::Middleware,
+    types::{H160, U64},
+};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abi, batch_requests,
+    checkpoint::sort_pool_variants,
+    dex::{Dex, DexVariant},
+    errors::CFMMError,
+    pool::Pool,
+    retry::{is_middleware_error, RetryPolicy},
+    sync,
+    throttle::{increment_or_sleep, RequestThrottle},
+};
+
+pub const UNISWAP_V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+//Max number of factory lookups per multicall
+const LOOKUP_BATCH_SIZE: usize = 100;
+
+//Counts of the entries processed during an import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    //Pools that were found and populated
+    pub found: usize,
+    //Pairs that do not have a pool on the dex
+    pub missing: usize,
+    //Lookups or pool data requests that failed, or entries that could not be parsed
+    pub failed: usize,
+    //Pools that were skipped because they are already in the registry or were found more than once
+    pub duplicates: usize,
+}
+
+//Subset of the Uniswap token list schema (https://tokenlists.org) needed to enumerate pairs
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenList {
+    pub tokens: Vec<TokenInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    pub chain_id: u64,
+    pub address: H160,
+}
+
+//A single line of a pool list in the JSON lines format, ex. {"address":"0xB4e1...C9Dc","variant":"UniswapV2"}
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PoolEntry {
+    pub address: H160,
+    pub variant: DexVariant,
+}
+
+//Gets all pools between each pair of tokens in the token list across the provided dexes.
+//Pools that are already in `existing_pools` are skipped.
+pub async fn import_pools_from_tokenlist<M: 'static + Middleware>(
+    token_list_json: &str,
+    dexes: &[Dex],
+    existing_pools: Option<&[Pool]>,
+    middleware: Arc<M>,
+) -> Result<(Vec<Pool>, ImportReport), CFMMError<M>> {
+    import_pools_from_tokenlist_with_throttle(
+        token_list_json,
+        dexes,
+        existing_pools,
+        0,
+        RetryPolicy::default(),
+        middleware,
+    )
+    .await
+}
+
+//Gets all pools between each pair of tokens in the token list across the provided dexes, batching the factory lookups via multicall.
+//Only tokens on the same chain as the middleware are paired. Pools that are already in `existing_pools` are skipped.
+//Rate limited requests are retried according to `retry_policy`, other middleware errors are returned.
+pub async fn import_pools_from_tokenlist_with_throttle<M: 'static + Middleware>(
+    token_list_json: &str,
+    dexes: &[Dex],
+    existing_pools: Option<&[Pool]>,
+    requests_per_second_limit: usize,
+    retry_policy: RetryPolicy,
+    middleware: Arc<M>,
+) -> Result<(Vec<Pool>, ImportReport), CFMMError<M>> {
+    let token_list: TokenList = serde_json::from_str(token_list_json)?;
+
+    let chain_id = middleware
+        .get_chainid()
+        .await
+        .map_err(CFMMError::MiddlewareError)?
+        .as_u64();
+
+    let mut seen_tokens = HashSet::new();
+    let tokens = token_list
+        .tokens
+        .into_iter()
+        .filter(|token| token.chain_id == chain_id && seen_tokens.insert(token.address))
+        .map(|token| token.address)
+        .collect::<Vec<H160>>();
+
+    let mut report = ImportReport::default();
+    let mut entries = vec![];
+
+    let request_throttle = Mutex::new(RequestThrottle::new(requests_per_second_limit));
+    let mut multicall = Multicall::new_with_chain_id(middleware.clone(), None, Some(chain_id))?;
+
+    //The lookups are generated for one batch at a time, since there can be millions of them for a large token list
+    let mut lookups = factory_lookups(&tokens, dexes).peekable();
+
+    while lookups.peek().is_some() {
+        multicall.clear_calls();
+        let mut variants = vec![];
+        for (token_a, token_b, dex) in lookups.by_ref().take(LOOKUP_BATCH_SIZE) {
+            match dex {
+                FactoryLookup::UniswapV2(factory) => {
+                    multicall.add_call(
+                        abi::IUniswapV2Factory::new(factory, middleware.clone())
+                            .get_pair(token_a, token_b),
+                        true,
+                    );
+                    variants.push(DexVariant::UniswapV2);
+                }
+
+                FactoryLookup::UniswapV3(factory, fee) => {
+                    multicall.add_call(
+                        abi::IUniswapV3Factory::new(factory, middleware.clone())
+                            .get_pool(token_a, token_b, fee),
+                        true,
+                    );
+                    variants.push(DexVariant::UniswapV3);
+                }
+            }
+        }
+
+        let multicall = &multicall;
+        let request_throttle = &request_throttle;
+        let results = retry_policy
+            .retry(|| async move {
+                increment_or_sleep(request_throttle, 1).await;
+                Ok(multicall.call_raw().await?)
+            })
+            .await?;
+
+        for (result, variant) in results.into_iter().zip(variants) {
+            match result.map(|token| token.into_address()) {
+                Ok(Some(address)) if address.is_zero() => report.missing += 1,
+                Ok(Some(address)) => entries.push(PoolEntry { address, variant }),
+                _ => report.failed += 1,
+            }
+        }
+    }
+
+    let pools = import_pool_entries(
+        entries,
+        existing_pools,
+        &mut report,
+        &retry_policy,
+        middleware,
+    )
+    .await?;
+
+    Ok((pools, report))
+}
+
+//Factory and fee tier to look up a pool on
+#[derive(Debug, Clone, Copy)]
+enum FactoryLookup {
+    UniswapV2(H160),
+    UniswapV3(H160, u32),
+}
+
+//Lazily generates a lookup for each pair of tokens on each dex, and on each fee tier for Uniswap V3 dexes
+fn factory_lookups<'a>(
+    tokens: &'a [H160],
+    dexes: &'a [Dex],
+) -> impl Iterator<Item = (H160, H160, FactoryLookup)> + 'a {
+    tokens.iter().enumerate().flat_map(move |(i, token_a)| {
+        tokens[i + 1..].iter().flat_map(move |token_b| {
+            dexes.iter().flat_map(move |dex| {
+                let lookups = match dex {
+                    Dex::UniswapV2(uniswap_v2_dex) => {
+                        vec![FactoryLookup::UniswapV2(uniswap_v2_dex.factory_address)]
+                    }
+                    Dex::UniswapV3(uniswap_v3_dex) => UNISWAP_V3_FEE_TIERS
+                        .iter()
+                        .map(|fee| FactoryLookup::UniswapV3(uniswap_v3_dex.factory_address, *fee))
+                        .collect(),
+                };
+
+                lookups
+                    .into_iter()
+                    .map(move |lookup| (*token_a, *token_b, lookup))
+            })
+        })
+    })
+}
+
+//Reads a pool list in the JSON lines format, with one `PoolEntry` per line, and populates the pool data via batched calls.
+//Blank lines are ignored and lines that can not be parsed are counted as failed.
+pub async fn import_pools_from_json_lines<M: 'static + Middleware, R: BufRead>(
+    reader: R,
+    existing_pools: Option<&[Pool]>,
+    middleware: Arc<M>,
+) -> Result<(Vec<Pool>, ImportReport), CFMMError<M>> {
+    let mut report = ImportReport::default();
+    let entries = parse_json_lines(reader, &mut report)?;
+
+    let pools = import_pool_entries(
+        entries,
+        existing_pools,
+        &mut report,
+        &RetryPolicy::default(),
+        middleware,
+    )
+    .await?;
+
+    Ok((pools, report))
+}
+
+pub fn parse_json_lines<R: BufRead>(
+    reader: R,
+    report: &mut ImportReport,
+) -> Result<Vec<PoolEntry>, std::io::Error> {
+    let mut entries = vec![];
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<PoolEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => report.failed += 1,
+        }
+    }
+
+    Ok(entries)
+}
+
+//Dedupes the entries against the existing pools and populates the pool data for the remaining entries
+async fn import_pool_entries<M: 'static + Middleware>(
+    entries: Vec<PoolEntry>,
+    existing_pools: Option<&[Pool]>,
+    report: &mut ImportReport,
+    retry_policy: &RetryPolicy,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    let mut seen: HashSet<H160> = existing_pools
+        .unwrap_or_default()
+        .iter()
+        .map(|pool| pool.address())
+        .collect();
+
+    let mut pools = vec![];
+    for entry in entries {
+        if !seen.insert(entry.address) {
+            report.duplicates += 1;
+            continue;
+        }
+
+        pools.push(Pool::new_empty(entry.address, entry.variant));
+    }
+
+    let current_block = retry_policy
+        .retry(|| {
+            let middleware = middleware.clone();
+            async move {
+                middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            }
+        })
+        .await?;
+
+    let total = pools.len();
+    let pools =
+        populate_pools_with_retry(pools, Some(current_block), retry_policy, middleware).await?;
+
+    report.found += pools.len();
+    report.failed += total - pools.len();
+
+    Ok(pools)
+}
+
+//Gets the pool data for each pool via batched calls at `block_number`, or at the latest block if `None`,
+//removing any pools that could not be populated
+pub async fn populate_pools<M: 'static + Middleware>(
+    pools: Vec<Pool>,
+    block_number: Option<U64>,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    populate_pools_with_retry(pools, block_number, &RetryPolicy::default(), middleware).await
+}
+
+//Populates the pools in batches, retrying rate limited batches according to `retry_policy`.
+//Pools in a batch that fails because of the pool contracts are left empty and removed, while middleware errors are returned.
+async fn populate_pools_with_retry<M: 'static + Middleware>(
+    pools: Vec<Pool>,
+    block_number: Option<U64>,
+    retry_policy: &RetryPolicy,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    let (mut uniswap_v2_pools, mut uniswap_v3_pools) = sort_pool_variants(pools);
+
+    for pools in uniswap_v2_pools.chunks_mut(127) {
+        let result = retry_policy
+            .retry_with(pools, |pools| {
+                batch_requests::uniswap_v2::get_pool_data_batch_request(
+                    pools,
+                    block_number,
+                    middleware.clone(),
+                )
+                .boxed()
+            })
+            .await;
+
+        match result {
+            Err(err) if is_middleware_error(&err) => return Err(err),
+            _ => {}
+        }
+    }
+
+    for pools in uniswap_v3_pools.chunks_mut(76) {
+        let result = retry_policy
+            .retry_with(pools, |pools| {
+                batch_requests::uniswap_v3::get_pool_data_batch_request(
+                    pools,
+                    block_number,
+                    middleware.clone(),
+                )
+                .boxed()
+            })
+            .await;
+
+        match result {
+            Err(err) if is_middleware_error(&err) => return Err(err),
+            _ => {}
+        }
+    }
+
+    uniswap_v2_pools.extend(uniswap_v3_pools);
+
+    Ok(sync::remove_empty_pools(uniswap_v2_pools))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        types::{Bytes, H160, U256},
+    };
+
+    use super::{
+        factory_lookups, import_pools_from_tokenlist, parse_json_lines, FactoryLookup,
+        ImportReport, TokenList,
+    };
+    use crate::{
+        abi,
+        dex::{Dex, DexVariant},
+        errors::CFMMError,
+        mock_middleware::MockMiddleware,
+        pool::Pool,
+    };
+
+    #[test]
+    fn test_parse_json_lines() {
+        let json_lines = r#"{"address":"0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc","variant":"UniswapV2"}
+
+{"address":"0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640","variant":"UniswapV3"}
+{"address":"0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640","variant":"Curve"}
+not json
+"#;
+
+        let mut report = ImportReport::default();
+        let entries = parse_json_lines(json_lines.as_bytes(), &mut report).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].variant, DexVariant::UniswapV3);
+        assert_eq!(
+            entries[0].address,
+            H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap()
+        );
+        assert_eq!(report.failed, 2);
+    }
+
+    #[test]
+    fn test_parse_token_list() {
+        let token_list_json = r#"{
+            "name": "Test List",
+            "timestamp": "2023-01-01T00:00:00.000Z",
+            "version": { "major": 1, "minor": 0, "patch": 0 },
+            "tokens": [
+                {
+                    "chainId": 1,
+                    "address": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+                    "name": "USD Coin",
+                    "symbol": "USDC",
+                    "decimals": 6
+                },
+                {
+                    "chainId": 1,
+                    "address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+                    "name": "Wrapped Ether",
+                    "symbol": "WETH",
+                    "decimals": 18
+                }
+            ]
+        }"#;
+
+        let token_list: TokenList = serde_json::from_str(token_list_json).unwrap();
+
+        assert_eq!(token_list.tokens.len(), 2);
+        assert_eq!(token_list.tokens[0].chain_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_pools_from_tokenlist() {
+        let factory = H160::from_low_u64_be(1);
+        let pair = H160::from_low_u64_be(2);
+        let token_a = H160::from_low_u64_be(10);
+        let token_b = H160::from_low_u64_be(11);
+        let token_c = H160::from_low_u64_be(12);
+        let token_d = H160::from_low_u64_be(13);
+
+        //Token D is on another chain, so it is never paired
+        let token_list_json = format!(
+            r#"{{"tokens": [
+                {{"chainId": 1, "address": "{:?}"}},
+                {{"chainId": 1, "address": "{:?}"}},
+                {{"chainId": 1, "address": "{:?}"}},
+                {{"chainId": 137, "address": "{:?}"}}
+            ]}}"#,
+            token_a, token_b, token_c, token_d
+        );
+
+        let get_pair = |token_a: H160, token_b: H160| -> Bytes {
+            abi::IUniswapV2Factory::new(factory, Arc::new(MockMiddleware::new(0)))
+                .get_pair(token_a, token_b)
+                .calldata()
+                .unwrap()
+        };
+
+        let pool_data = ethers::abi::encode(&[Token::Array(vec![Token::Tuple(vec![
+            Token::Address(token_a),
+            Token::Uint(U256::from(18)),
+            Token::Address(token_b),
+            Token::Uint(U256::from(6)),
+            Token::Uint(U256::from(1000)),
+            Token::Uint(U256::from(2000)),
+        ])])]);
+
+        //A/B has a pair, A/C does not and the lookup for B/C fails
+        let middleware = Arc::new(
+            MockMiddleware::new(100)
+                .with_response(
+                    factory,
+                    get_pair(token_a, token_b),
+                    ethers::abi::encode(&[Token::Address(pair)]).into(),
+                )
+                .with_response(
+                    factory,
+                    get_pair(token_a, token_c),
+                    ethers::abi::encode(&[Token::Address(H160::zero())]).into(),
+                ),
+        );
+        middleware.push_deploy_response(pool_data.into());
+
+        let dexes = [Dex::new(factory, DexVariant::UniswapV2, 0, None)];
+
+        let (pools, report) =
+            import_pools_from_tokenlist(&token_list_json, &dexes, None, middleware.clone())
+                .await
+                .unwrap();
+
+        assert_eq!(
+            report,
+            ImportReport {
+                found: 1,
+                missing: 1,
+                failed: 1,
+                duplicates: 0,
+            }
+        );
+
+        assert_eq!(pools.len(), 1);
+        match &pools[0] {
+            Pool::UniswapV2(pool) => {
+                assert_eq!(pool.address, pair);
+                assert_eq!(pool.token_a, token_a);
+                assert_eq!(pool.reserve_1, 2000);
+                assert_eq!(pool.last_synced_block, 100);
+            }
+            _ => panic!("Expected a Uniswap V2 pool"),
+        }
+
+        //Chain id, one multicall for the lookups and the block number
+        assert_eq!(middleware.requests(), 3);
+
+        //Pools that are already in the registry are skipped
+        let (pools, report) =
+            import_pools_from_tokenlist(&token_list_json, &dexes, Some(&pools), middleware)
+                .await
+                .unwrap();
+
+        assert!(pools.is_empty());
+        assert_eq!(report.duplicates, 1);
+    }
+
+    #[test]
+    fn test_factory_lookups() {
+        let tokens = (10..14).map(H160::from_low_u64_be).collect::<Vec<H160>>();
+        let dexes = [
+            Dex::new(H160::from_low_u64_be(1), DexVariant::UniswapV2, 0, None),
+            Dex::new(H160::from_low_u64_be(2), DexVariant::UniswapV3, 0, None),
+        ];
+
+        let lookups = factory_lookups(&tokens, &dexes).collect::<Vec<_>>();
+
+        //6 pairs, each looked up once on the V2 dex and once per fee tier on the V3 dex
+        assert_eq!(lookups.len(), 6 * 5);
+        assert!(matches!(
+            lookups[0],
+            (token_a, token_b, FactoryLookup::UniswapV2(_)) if token_a == tokens[0] && token_b == tokens[1]
+        ));
+        assert!(matches!(
+            lookups[4],
+            (_, _, FactoryLookup::UniswapV3(_, 10000))
+        ));
+        assert!(lookups
+            .iter()
+            .all(|(token_a, token_b, _)| token_a < token_b));
+    }
+
+    #[tokio::test]
+    async fn test_import_pools_from_tokenlist_returns_middleware_errors() {
+        let factory = H160::from_low_u64_be(1);
+        let (token_a, token_b) = (H160::from_low_u64_be(10), H160::from_low_u64_be(11));
+
+        let token_list_json = format!(
+            r#"{{"tokens": [{{"chainId": 1, "address": "{:?}"}}, {{"chainId": 1, "address": "{:?}"}}]}}"#,
+            token_a, token_b
+        );
+
+        let get_pair = abi::IUniswapV2Factory::new(factory, Arc::new(MockMiddleware::new(0)))
+            .get_pair(token_a, token_b)
+            .calldata()
+            .unwrap();
+
+        //The pair is found, but there is no response for the pool data request, so it fails in the provider
+        let middleware = Arc::new(MockMiddleware::new(100).with_response(
+            factory,
+            get_pair,
+            ethers::abi::encode(&[Token::Address(H160::from_low_u64_be(2))]).into(),
+        ));
+
+        let dexes = [Dex::new(factory, DexVariant::UniswapV2, 0, None)];
+
+        let result = import_pools_from_tokenlist(&token_list_json, &dexes, None, middleware).await;
+
+        assert!(matches!(result, Err(CFMMError::ProviderError(_))));
+    }
+}
@@ -1,9 +1,24 @@
 mod abi;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod checkpoint;
 pub mod dex;
 pub mod errors;
+pub mod fee_monitor;
+#[cfg(feature = "fork-test-utils")]
+pub mod fork_test_utils;
+pub mod health;
+pub mod import;
+pub mod math;
+#[cfg(test)]
+mod mock_middleware;
 pub mod pool;
+pub mod progress;
+pub mod quote;
+pub mod retry;
 pub mod sync;
+#[cfg(test)]
+mod test_utils;
 pub mod throttle;
 pub use pool::simulate_route;
 pub use pool::simulate_route_mut;
@@ -0,0 +1,206 @@
+//Conversions between ticks, sqrt prices and decimal adjusted prices for Uniswap V3 style pools.
+//Prices are quoted as the amount of token 1 per token 0, adjusted for the decimals of each token.
+use ethers::types::U256;
+use uniswap_v3_math::error::UniswapV3MathError;
+
+pub const Q96_F64: f64 = 79228162514264337593543950336.0;
+
+//Returns the decimal adjusted price at the tick, from the sqrt price at the tick
+pub fn tick_to_price(
+    tick: i32,
+    token_0_decimals: u8,
+    token_1_decimals: u8,
+) -> Result<f64, UniswapV3MathError> {
+    Ok(sqrt_price_x96_to_price(
+        uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(tick)?,
+        token_0_decimals,
+        token_1_decimals,
+    ))
+}
+
+//Returns the greatest tick with a price less than or equal to the decimal adjusted price
+pub fn price_to_tick(
+    price: f64,
+    token_0_decimals: u8,
+    token_1_decimals: u8,
+) -> Result<i32, UniswapV3MathError> {
+    uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(price_to_sqrt_price_x96(
+        price,
+        token_0_decimals,
+        token_1_decimals,
+    )?)
+}
+
+//Returns the decimal adjusted price from a Q64.96 sqrt price
+pub fn sqrt_price_x96_to_price(
+    sqrt_price_x96: U256,
+    token_0_decimals: u8,
+    token_1_decimals: u8,
+) -> f64 {
+    let sqrt_price = u256_to_f64(sqrt_price_x96) / Q96_F64;
+
+    sqrt_price * sqrt_price * 10_f64.powi(token_0_decimals as i32 - token_1_decimals as i32)
+}
+
+//Returns the Q64.96 sqrt price from a decimal adjusted price
+pub fn price_to_sqrt_price_x96(
+    price: f64,
+    token_0_decimals: u8,
+    token_1_decimals: u8,
+) -> Result<U256, UniswapV3MathError> {
+    let raw_price = price * 10_f64.powi(token_1_decimals as i32 - token_0_decimals as i32);
+    let sqrt_price_x96 = raw_price.sqrt() * Q96_F64;
+
+    //Checked first since NaN, from negative or NaN prices, is not finite
+    if sqrt_price_x96.is_nan() || sqrt_price_x96 < 1.0 {
+        return Err(UniswapV3MathError::SqrtPriceIsZero);
+    }
+
+    //Sqrt prices are stored as a uint160
+    if sqrt_price_x96.is_infinite() || sqrt_price_x96 >= 2_f64.powi(160) {
+        return Err(UniswapV3MathError::SafeCastToU160Overflow);
+    }
+
+    Ok(f64_to_u256(sqrt_price_x96))
+}
+
+pub fn u256_to_f64(x: U256) -> f64 {
+    x.0.iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 18446744073709551616.0 + *limb as f64)
+}
+
+//Truncates a finite, non negative f64 to a U256
+pub fn f64_to_u256(x: f64) -> U256 {
+    if x.is_nan() || x < 1.0 || x.is_infinite() {
+        return U256::zero();
+    }
+
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1075;
+    let mantissa = U256::from((bits & ((1 << 52) - 1)) | (1 << 52));
+
+    if exponent >= 0 {
+        mantissa << exponent as usize
+    } else {
+        mantissa >> (-exponent) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U256;
+    use proptest::prelude::*;
+    use uniswap_v3_math::error::UniswapV3MathError;
+    use uniswap_v3_math::tick_math::{get_sqrt_ratio_at_tick, MAX_TICK, MIN_TICK};
+
+    use super::{
+        f64_to_u256, price_to_sqrt_price_x96, price_to_tick, sqrt_price_x96_to_price,
+        tick_to_price, u256_to_f64,
+    };
+
+    #[test]
+    fn test_tick_to_price() {
+        let assert_price =
+            |tick: i32, token_0_decimals: u8, token_1_decimals: u8, expected: f64| {
+                let price = tick_to_price(tick, token_0_decimals, token_1_decimals).unwrap();
+                assert!(((price - expected) / expected).abs() < 1e-12);
+            };
+
+        assert_eq!(tick_to_price(0, 18, 18).unwrap(), 1.0);
+        assert_price(0, 6, 18, 1e-12);
+        assert_price(0, 18, 6, 1e12);
+        assert_price(1, 18, 18, 1.0001);
+
+        //Decimal differences above i8::MAX
+        assert_price(0, 200, 0, 1e200);
+        assert_price(0, 0, 200, 1e-200);
+
+        //The price at the tick bounds matches the sqrt price at the tick
+        for tick in [MIN_TICK, MAX_TICK] {
+            assert_eq!(
+                tick_to_price(tick, 18, 18).unwrap(),
+                sqrt_price_x96_to_price(get_sqrt_ratio_at_tick(tick).unwrap(), 18, 18)
+            );
+        }
+
+        assert!(matches!(
+            tick_to_price(MAX_TICK + 1, 18, 18),
+            Err(UniswapV3MathError::T)
+        ));
+    }
+
+    #[test]
+    fn test_sqrt_price_x96_to_price() {
+        //Tick of the USDC/WETH 0.05% pool
+        let sqrt_price_x96 = get_sqrt_ratio_at_tick(202919).unwrap();
+        let price = sqrt_price_x96_to_price(sqrt_price_x96, 6, 18);
+        let expected_price = 1.0001_f64.powi(202919) * 1e-12;
+
+        assert!(((price - expected_price) / expected_price).abs() < 1e-9);
+        assert_eq!(
+            sqrt_price_x96_to_price(get_sqrt_ratio_at_tick(0).unwrap(), 18, 18),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_price_to_sqrt_price_x96_errors() {
+        assert!(matches!(
+            price_to_sqrt_price_x96(0.0, 18, 18),
+            Err(UniswapV3MathError::SqrtPriceIsZero)
+        ));
+        assert!(matches!(
+            price_to_sqrt_price_x96(-1.0, 18, 18),
+            Err(UniswapV3MathError::SqrtPriceIsZero)
+        ));
+        assert!(matches!(
+            price_to_sqrt_price_x96(f64::NAN, 18, 18),
+            Err(UniswapV3MathError::SqrtPriceIsZero)
+        ));
+        assert!(matches!(
+            price_to_sqrt_price_x96(f64::INFINITY, 18, 18),
+            Err(UniswapV3MathError::SafeCastToU160Overflow)
+        ));
+        assert!(price_to_tick(1e60, 18, 18).is_err());
+    }
+
+    #[test]
+    fn test_f64_u256_conversion() {
+        assert_eq!(f64_to_u256(0.5), U256::zero());
+        assert_eq!(f64_to_u256(12345.9), U256::from(12345));
+        assert_eq!(f64_to_u256(2_f64.powi(100)), U256::one() << 100);
+        assert_eq!(u256_to_f64(U256::one() << 100), 2_f64.powi(100));
+    }
+
+    proptest! {
+        #[test]
+        fn test_tick_price_round_trip(
+            tick in (MIN_TICK + 1)..MAX_TICK,
+            token_0_decimals in 0u8..=18,
+            token_1_decimals in 0u8..=18,
+        ) {
+            let price = tick_to_price(tick, token_0_decimals, token_1_decimals).unwrap();
+            let round_trip_tick = price_to_tick(price, token_0_decimals, token_1_decimals).unwrap();
+
+            prop_assert!((round_trip_tick - tick).abs() <= 1);
+        }
+
+        #[test]
+        fn test_sqrt_price_round_trip(
+            tick in (MIN_TICK + 1)..MAX_TICK,
+            token_0_decimals in 0u8..=18,
+            token_1_decimals in 0u8..=18,
+        ) {
+            let sqrt_price_x96 = get_sqrt_ratio_at_tick(tick).unwrap();
+            let price = sqrt_price_x96_to_price(sqrt_price_x96, token_0_decimals, token_1_decimals);
+            let round_trip_sqrt_price_x96 =
+                price_to_sqrt_price_x96(price, token_0_decimals, token_1_decimals).unwrap();
+
+            let round_trip_tick =
+                uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(round_trip_sqrt_price_x96).unwrap();
+
+            prop_assert!((round_trip_tick - tick).abs() <= 1);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//Middleware used in tests that answers `get_block_number`, `get_chainid`, `getReserves`, multicall and batch request calls without a node.
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use ethers::{
+    abi::{ParamType, Token},
+    contract::MULTICALL_ADDRESS,
+    providers::{Middleware, MockProvider, Provider, ProviderError},
+    types::{transaction::eip2718::TypedTransaction, BlockId, Bytes, H160, U256, U64},
+};
+
+//Selector for `getReserves()`
+const GET_RESERVES_SELECTOR: [u8; 4] = [0x09, 0x02, 0xf1, 0xac];
+//Selector for `aggregate3((address,bool,bytes)[])`
+const AGGREGATE_3_SELECTOR: [u8; 4] = [0x82, 0xad, 0x56, 0xcb];
+
+#[derive(Debug)]
+pub struct MockMiddleware {
+    inner: Provider<MockProvider>,
+    block_number: u64,
+    chain_id: u64,
+    //Reserves returned by `getReserves` for each pool. Calls to any other address return empty data, which fails to decode.
+    reserves: HashMap<H160, (u128, u128)>,
+    //Return data for calls to an address with the given calldata, including the calls aggregated in a multicall.
+    //Aggregated calls without a response fail.
+    responses: HashMap<(H160, Bytes), Bytes>,
+    //Errors returned in order by the next requests before falling back to the mocked responses
+    errors: Mutex<VecDeque<ProviderError>>,
+    requests: AtomicUsize,
+}
+
+impl MockMiddleware {
+    pub fn new(block_number: u64) -> MockMiddleware {
+        MockMiddleware {
+            inner: Provider::new(MockProvider::new()),
+            block_number,
+            chain_id: 1,
+            reserves: HashMap::new(),
+            responses: HashMap::new(),
+            errors: Mutex::new(VecDeque::new()),
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_reserves(mut self, pool: H160, reserve_0: u128, reserve_1: u128) -> MockMiddleware {
+        self.reserves.insert(pool, (reserve_0, reserve_1));
+        self
+    }
+
+    pub fn with_response(mut self, to: H160, calldata: Bytes, response: Bytes) -> MockMiddleware {
+        self.responses.insert((to, calldata), response);
+        self
+    }
+
+    //Batch request contracts are deployed in a call made directly on the inner provider, so their return data is queued on the
+    //mock provider, which answers the most recently pushed response first. These calls are not counted in `requests`.
+    pub fn push_deploy_response(&self, response: Bytes) {
+        self.inner
+            .as_ref()
+            .push::<Bytes, _>(response)
+            .expect("Could not push deploy response");
+    }
+
+    pub fn push_error(&self, error: ProviderError) {
+        self.errors.lock().unwrap().push_back(error);
+    }
+
+    //Number of requests made, including the ones that returned an error
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    fn next_error(&self) -> Option<ProviderError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.errors.lock().unwrap().pop_front()
+    }
+}
+
+#[async_trait]
+impl Middleware for MockMiddleware {
+    type Error = ProviderError;
+    type Provider = MockProvider;
+    type Inner = Provider<MockProvider>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        if let Some(error) = self.next_error() {
+            return Err(error);
+        }
+
+        Ok(U64::from(self.block_number))
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        if let Some(error) = self.next_error() {
+            return Err(error);
+        }
+
+        Ok(U256::from(self.chain_id))
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        _block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        if let Some(error) = self.next_error() {
+            return Err(error);
+        }
+
+        let data = tx.data().cloned().unwrap_or_default();
+
+        let to = match tx.to_addr() {
+            Some(to) => *to,
+            None => return Ok(Bytes::new()),
+        };
+
+        if to == MULTICALL_ADDRESS && data.starts_with(&AGGREGATE_3_SELECTOR) {
+            return Ok(self.aggregate_3(&data[4..]));
+        }
+
+        Ok(self.response(to, data).unwrap_or_default())
+    }
+}
+
+impl MockMiddleware {
+    fn response(&self, to: H160, data: Bytes) -> Option<Bytes> {
+        if data.starts_with(&GET_RESERVES_SELECTOR) {
+            if let Some((reserve_0, reserve_1)) = self.reserves.get(&to) {
+                return Some(
+                    ethers::abi::encode(&[
+                        Token::Uint(U256::from(*reserve_0)),
+                        Token::Uint(U256::from(*reserve_1)),
+                        Token::Uint(U256::zero()),
+                    ])
+                    .into(),
+                );
+            }
+        }
+
+        self.responses.get(&(to, data)).cloned()
+    }
+
+    //Answers each aggregated call like a direct call, failing the calls without a response
+    fn aggregate_3(&self, data: &[u8]) -> Bytes {
+        let calls = ethers::abi::decode(
+            &[ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Address,
+                ParamType::Bool,
+                ParamType::Bytes,
+            ])))],
+            data,
+        )
+        .expect("Could not decode aggregate3 calls");
+
+        let mut results = vec![];
+        for call in calls[0].clone().into_array().unwrap() {
+            let call = call.into_tuple().unwrap();
+            let target = call[0].clone().into_address().unwrap();
+            let calldata = Bytes::from(call[2].clone().into_bytes().unwrap());
+
+            let result = self.response(target, calldata);
+            results.push(Token::Tuple(vec![
+                Token::Bool(result.is_some()),
+                Token::Bytes(result.unwrap_or_default().to_vec()),
+            ]));
+        }
+
+        ethers::abi::encode(&[Token::Array(results)]).into()
+    }
+}
@@ -2,22 +2,24 @@ use std::{cmp::Ordering, sync::Arc};
 
 use ethers::{
     providers::Middleware,
-    types::{Log, H160, U256},
+    types::{Log, H160, U256, U64},
 };
 
 use crate::{
     dex::{self, DexVariant},
-    errors::{ArithmeticError, CFMMError},
+    errors::{ArithmeticError, CFMMError, SwapSimulationError},
 };
 
 pub mod fixed_point_math;
+pub mod tick_cache;
 pub mod uniswap_v2;
 pub mod uniswap_v3;
 use serde::{Deserialize, Serialize};
+use tick_cache::{TickCacheBound, TickWordCache};
 pub use uniswap_v2::UniswapV2Pool;
 pub use uniswap_v3::UniswapV3Pool;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Pool {
     UniswapV2(UniswapV2Pool),
     UniswapV3(UniswapV3Pool),
@@ -41,6 +43,20 @@ impl Pool {
         }
     }
 
+    //Creates a pool with only the address set, to be populated via batched calls
+    pub fn new_empty(address: H160, dex_variant: DexVariant) -> Pool {
+        match dex_variant {
+            DexVariant::UniswapV2 => Pool::UniswapV2(UniswapV2Pool {
+                address,
+                ..Default::default()
+            }),
+            DexVariant::UniswapV3 => Pool::UniswapV3(UniswapV3Pool {
+                address,
+                ..Default::default()
+            }),
+        }
+    }
+
     pub fn fee(&self) -> u32 {
         match self {
             Pool::UniswapV2(pool) => pool.fee(),
@@ -95,11 +111,22 @@ impl Pool {
         }
     }
 
+    pub async fn sync_pool_at_block<M: Middleware>(
+        &mut self,
+        block_number: U64,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        match self {
+            Pool::UniswapV2(pool) => pool.sync_pool_at_block(block_number, middleware).await,
+            Pool::UniswapV3(pool) => pool.sync_pool_at_block(block_number, middleware).await,
+        }
+    }
+
     //Get price of base token per pair token
     pub fn calculate_price(&self, base_token: H160) -> Result<f64, ArithmeticError> {
         match self {
             Pool::UniswapV2(pool) => pool.calculate_price(base_token),
-            Pool::UniswapV3(pool) => Ok(pool.calculate_price(base_token)),
+            Pool::UniswapV3(pool) => pool.calculate_price(base_token),
         }
     }
 
@@ -121,6 +148,22 @@ impl Pool {
         }
     }
 
+    //Block number that the pool state was last synced at
+    pub fn last_synced_block(&self) -> u64 {
+        match self {
+            Pool::UniswapV2(pool) => pool.last_synced_block,
+            Pool::UniswapV3(pool) => pool.last_synced_block,
+        }
+    }
+
+    pub fn set_last_synced_block(&mut self, block_number: u64) {
+        match self {
+            Pool::UniswapV2(pool) => pool.last_synced_block = block_number,
+            Pool::UniswapV3(pool) => pool.last_synced_block = block_number,
+        }
+    }
+
+    //V3 pools are simulated from the synced tick window, fetching the tick data if the swap leaves the window
     pub async fn simulate_swap<M: Middleware>(
         &self,
         token_in: H160,
@@ -128,8 +171,17 @@ impl Pool {
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
         match self {
-            Pool::UniswapV2(pool) => Ok(pool.simulate_swap(token_in, amount_in)),
-            Pool::UniswapV3(pool) => pool.simulate_swap(token_in, amount_in, middleware).await,
+            Pool::UniswapV2(pool) => Ok(pool.simulate_swap(token_in, amount_in)?),
+            Pool::UniswapV3(pool) => match pool.simulate_swap(token_in, amount_in) {
+                Err(SwapSimulationError::TickDataOutOfRange { .. }) => {
+                    //The words are only needed for this swap, so the cache is not bounded
+                    let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
+
+                    pool.simulate_swap_with_cache(token_in, amount_in, &mut tick_cache, middleware)
+                        .await
+                }
+                result => Ok(result?),
+            },
         }
     }
 
@@ -140,11 +192,21 @@ impl Pool {
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
         match self {
-            Pool::UniswapV2(pool) => Ok(pool.simulate_swap_mut(token_in, amount_in)),
-            Pool::UniswapV3(pool) => {
-                pool.simulate_swap_mut(token_in, amount_in, middleware)
+            Pool::UniswapV2(pool) => Ok(pool.simulate_swap_mut(token_in, amount_in)?),
+            Pool::UniswapV3(pool) => match pool.simulate_swap_mut(token_in, amount_in) {
+                Err(SwapSimulationError::TickDataOutOfRange { .. }) => {
+                    let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
+
+                    pool.simulate_swap_mut_with_cache(
+                        token_in,
+                        amount_in,
+                        &mut tick_cache,
+                        middleware,
+                    )
                     .await
-            }
+                }
+                result => Ok(result?),
+            },
         }
     }
 }
@@ -0,0 +1,749 @@
+//Size bounded cache of Uniswap V3 tick bitmap words, used to simulate swaps without fetching tick data on every call.
+//When the cache is full, the word furthest from the current tick of its pool is evicted, with ties broken by least recently used.
+//With a global bound, the word is evicted from the pool of the least recently used word so that eviction does not scan the cache.
+//Only minting and burning liquidity changes the tick bitmap and liquidity net, so swaps and resyncs leave the cached words valid.
+//Pass the Mint and Burn logs of each pool to `update_from_log` (or call `remove_pool`) to invalidate the words they change.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use ethers::{
+    contract::{Multicall, MULTICALL_ADDRESS},
+    providers::Middleware,
+    types::{Log, H160, I256, U256, U64},
+};
+
+use crate::{abi, errors::CFMMError};
+
+use super::{
+    uniswap_v3::{BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE},
+    UniswapV3Pool,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TickWord {
+    pub bitmap: U256,
+    //Liquidity net for each initialized tick in the word
+    pub liquidity_net: HashMap<i32, i128>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickCacheBound {
+    //Max number of words cached for each pool
+    PerPool(usize),
+    //Max number of words cached across all pools
+    Global(usize),
+}
+
+impl TickCacheBound {
+    pub fn max_words(&self) -> usize {
+        match self {
+            TickCacheBound::PerPool(max_words) | TickCacheBound::Global(max_words) => *max_words,
+        }
+    }
+}
+
+//Counted when the `metrics` feature is enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TickCacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug, Clone)]
+struct CachedWord {
+    word: TickWord,
+    last_used: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TickWordCache {
+    bound: TickCacheBound,
+    words: HashMap<(H160, i16), CachedWord>,
+    //Cached word positions for each pool, ordered so that the words furthest from the current tick are at either end
+    pool_words: HashMap<H160, BTreeSet<i16>>,
+    //Cached words ordered by when they were last used, used to pick the pool to evict from with a global bound
+    lru: BTreeMap<u64, (H160, i16)>,
+    //Word position of the current tick for each pool, used to decide which words to evict
+    current_words: HashMap<H160, i16>,
+    clock: u64,
+    #[cfg(feature = "metrics")]
+    metrics: TickCacheMetrics,
+}
+
+impl TickWordCache {
+    pub fn new(bound: TickCacheBound) -> TickWordCache {
+        TickWordCache {
+            bound,
+            words: HashMap::new(),
+            pool_words: HashMap::new(),
+            lru: BTreeMap::new(),
+            current_words: HashMap::new(),
+            clock: 0,
+            #[cfg(feature = "metrics")]
+            metrics: TickCacheMetrics::default(),
+        }
+    }
+
+    pub fn bound(&self) -> TickCacheBound {
+        self.bound
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> TickCacheMetrics {
+        self.metrics
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn contains(&self, pool: H160, word_position: i16) -> bool {
+        self.words.contains_key(&(pool, word_position))
+    }
+
+    //Returns the cached word, counting a hit or a miss
+    pub fn get(&mut self, pool: &UniswapV3Pool, word_position: i16) -> Option<&TickWord> {
+        self.clock += 1;
+        self.current_words
+            .insert(pool.address, current_word_position(pool));
+
+        let key = (pool.address, word_position);
+        let cached_word = match self.words.get_mut(&key) {
+            Some(cached_word) => cached_word,
+            None => {
+                #[cfg(feature = "metrics")]
+                {
+                    self.metrics.misses += 1;
+                }
+                return None;
+            }
+        };
+
+        self.lru.remove(&cached_word.last_used);
+        self.lru.insert(self.clock, key);
+        cached_word.last_used = self.clock;
+
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics.hits += 1;
+        }
+
+        Some(&cached_word.word)
+    }
+
+    //Inserts the word, evicting words until the cache is within its bound. The inserted word is never evicted by its own insert.
+    pub fn insert(&mut self, pool: &UniswapV3Pool, word_position: i16, word: TickWord) {
+        self.clock += 1;
+        self.current_words
+            .insert(pool.address, current_word_position(pool));
+
+        let key = (pool.address, word_position);
+        if let Some(cached_word) = self.words.insert(
+            key,
+            CachedWord {
+                word,
+                last_used: self.clock,
+            },
+        ) {
+            self.lru.remove(&cached_word.last_used);
+        }
+
+        self.lru.insert(self.clock, key);
+        self.pool_words
+            .entry(pool.address)
+            .or_default()
+            .insert(word_position);
+
+        while self.over_bound(pool.address) {
+            if !self.evict(key) {
+                break;
+            }
+        }
+    }
+
+    //Removes the words that a Mint or Burn log of the pool changes, returning false if the log is not a Mint or Burn log of the pool
+    pub fn update_from_log(&mut self, pool: &UniswapV3Pool, log: &Log) -> bool {
+        if log.address != pool.address
+            || log.topics.len() < 4
+            || (log.topics[0] != MINT_EVENT_SIGNATURE && log.topics[0] != BURN_EVENT_SIGNATURE)
+        {
+            return false;
+        }
+
+        //The lower and upper ticks are the second and third indexed params of both events
+        for topic in &log.topics[2..4] {
+            let tick = I256::from_raw(U256::from_big_endian(topic.as_bytes())).low_i32();
+            self.remove_tick(pool, tick);
+        }
+
+        true
+    }
+
+    //Removes the word containing the tick, which must be called when liquidity is added or removed at the tick
+    pub fn remove_tick(&mut self, pool: &UniswapV3Pool, tick: i32) {
+        let word_position =
+            uniswap_v3_math::tick_bit_map::position(compress_tick(tick, pool.tick_spacing)).0;
+
+        self.remove((pool.address, word_position));
+    }
+
+    //Removes all cached words for the pool
+    pub fn remove_pool(&mut self, pool: H160) {
+        if let Some(word_positions) = self.pool_words.remove(&pool) {
+            for word_position in word_positions {
+                if let Some(cached_word) = self.words.remove(&(pool, word_position)) {
+                    self.lru.remove(&cached_word.last_used);
+                }
+            }
+        }
+
+        self.current_words.remove(&pool);
+    }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.pool_words.clear();
+        self.lru.clear();
+        self.current_words.clear();
+    }
+
+    fn remove(&mut self, key: (H160, i16)) {
+        if let Some(cached_word) = self.words.remove(&key) {
+            self.lru.remove(&cached_word.last_used);
+        }
+
+        if let Some(word_positions) = self.pool_words.get_mut(&key.0) {
+            word_positions.remove(&key.1);
+
+            if word_positions.is_empty() {
+                self.pool_words.remove(&key.0);
+            }
+        }
+    }
+
+    fn over_bound(&self, pool: H160) -> bool {
+        match self.bound {
+            TickCacheBound::PerPool(max_words) => self
+                .pool_words
+                .get(&pool)
+                .is_some_and(|word_positions| word_positions.len() > max_words),
+            TickCacheBound::Global(max_words) => self.words.len() > max_words,
+        }
+    }
+
+    //Evicts the word furthest from the current tick of its pool, returning false if there is nothing to evict.
+    //With a global bound, the word is evicted from the pool of the least recently used word.
+    fn evict(&mut self, inserted: (H160, i16)) -> bool {
+        let pool = match self.bound {
+            TickCacheBound::PerPool(_) => inserted.0,
+            TickCacheBound::Global(_) => match self.lru.values().find(|key| **key != inserted) {
+                Some((pool, _)) => *pool,
+                None => return false,
+            },
+        };
+
+        let word_positions = match self.pool_words.get(&pool) {
+            Some(word_positions) => word_positions,
+            None => return false,
+        };
+
+        //The furthest word is at one of the ends of the ordered word positions
+        let not_inserted = |word_position: &&i16| (pool, **word_position) != inserted;
+        let candidates = [
+            word_positions.iter().find(not_inserted),
+            word_positions.iter().rev().find(not_inserted),
+        ];
+
+        let current_word = self.current_words.get(&pool).copied().unwrap_or(0);
+        let evicted = candidates
+            .into_iter()
+            .flatten()
+            .max_by_key(|word_position| {
+                let distance = (**word_position as i32 - current_word as i32).abs();
+                let last_used = self.words[&(pool, **word_position)].last_used;
+
+                (distance, std::cmp::Reverse(last_used))
+            })
+            .map(|word_position| (pool, *word_position));
+
+        if let Some(key) = evicted {
+            self.remove(key);
+
+            #[cfg(feature = "metrics")]
+            {
+                self.metrics.evictions += 1;
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+//Word position in the tick bitmap of the pool's current tick
+pub fn current_word_position(pool: &UniswapV3Pool) -> i16 {
+    uniswap_v3_math::tick_bit_map::position(compress_tick(pool.tick, pool.tick_spacing)).0
+}
+
+//Rounds the tick towards negative infinity to a multiple of the tick spacing, divided by the tick spacing
+pub fn compress_tick(tick: i32, tick_spacing: i32) -> i32 {
+    if tick_spacing == 0 {
+        return tick;
+    }
+
+    if tick < 0 && tick % tick_spacing != 0 {
+        (tick / tick_spacing) - 1
+    } else {
+        tick / tick_spacing
+    }
+}
+
+//Gets the tick bitmap word, then the liquidity net of each initialized tick within the word in a single multicall.
+//Multicall3 is deployed at the same address on all supported chains, so the chain id is not requested.
+pub async fn fetch_tick_word<M: Middleware>(
+    pool: &UniswapV3Pool,
+    word_position: i16,
+    block_number: Option<U64>,
+    middleware: Arc<M>,
+) -> Result<TickWord, CFMMError<M>> {
+    let v3_pool = abi::IUniswapV3Pool::new(pool.address, middleware.clone());
+
+    let bitmap = pool
+        .get_word(word_position, block_number, middleware.clone())
+        .await?;
+
+    let ticks = (0..256)
+        .filter(|bit_position| bitmap.bit(*bit_position))
+        .map(|bit_position| (word_position as i32 * 256 + bit_position as i32) * pool.tick_spacing)
+        .collect::<Vec<i32>>();
+
+    let mut liquidity_net = HashMap::new();
+    if !ticks.is_empty() {
+        let mut multicall =
+            Multicall::new_with_chain_id(middleware, Some(MULTICALL_ADDRESS), None::<u64>)?;
+
+        if let Some(block_number) = block_number {
+            multicall = multicall.block(block_number);
+        }
+
+        for tick in ticks.iter() {
+            multicall.add_call(v3_pool.ticks(*tick), false);
+        }
+
+        let tick_infos = multicall
+            .call_array::<(u128, i128, U256, U256, i64, U256, u32, bool)>()
+            .await?;
+
+        for (tick, tick_info) in ticks.into_iter().zip(tick_infos) {
+            liquidity_net.insert(tick, tick_info.1);
+        }
+    }
+
+    Ok(TickWord {
+        bitmap,
+        liquidity_net,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        types::{Bytes, Log, H160, H256, I256, U256, U64},
+    };
+    use uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick;
+
+    #[cfg(feature = "metrics")]
+    use super::TickCacheMetrics;
+    use super::{compress_tick, fetch_tick_word, TickCacheBound, TickWord, TickWordCache};
+    use crate::{
+        abi,
+        errors::SwapSimulationError,
+        mock_middleware::MockMiddleware,
+        pool::{
+            uniswap_v3::{
+                TickInfo, TickWindow, BURN_EVENT_SIGNATURE, MINT_EVENT_SIGNATURE,
+                SWAP_EVENT_SIGNATURE,
+            },
+            UniswapV3Pool,
+        },
+    };
+
+    fn pool(address: u64, tick: i32) -> UniswapV3Pool {
+        UniswapV3Pool {
+            address: H160::from_low_u64_be(address),
+            tick,
+            tick_spacing: 1,
+            ..Default::default()
+        }
+    }
+
+    fn word() -> TickWord {
+        TickWord {
+            bitmap: U256::one(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_evicts_word_furthest_from_current_tick() {
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(3));
+        let pool = pool(1, 0);
+
+        cache.insert(&pool, 0, word());
+        cache.insert(&pool, -5, word());
+        cache.insert(&pool, 1, word());
+        cache.insert(&pool, -1, word());
+
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains(pool.address, -5));
+        assert!(cache.contains(pool.address, -1));
+
+        assert!(cache.get(&pool, 0).is_some());
+        assert!(cache.get(&pool, -5).is_none());
+
+        #[cfg(feature = "metrics")]
+        assert_eq!(
+            cache.metrics(),
+            TickCacheMetrics {
+                hits: 1,
+                misses: 1,
+                evictions: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_on_tie() {
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(2));
+        let pool = pool(1, 0);
+
+        cache.insert(&pool, -1, word());
+        cache.insert(&pool, 1, word());
+        cache.get(&pool, -1);
+        cache.insert(&pool, 0, word());
+
+        assert!(cache.contains(pool.address, -1));
+        assert!(!cache.contains(pool.address, 1));
+    }
+
+    #[test]
+    fn test_per_pool_and_global_bounds() {
+        let pool_a = pool(1, 0);
+        let pool_b = pool(2, 0);
+
+        let mut per_pool_cache = TickWordCache::new(TickCacheBound::PerPool(1));
+        per_pool_cache.insert(&pool_a, 0, word());
+        per_pool_cache.insert(&pool_b, 0, word());
+        assert_eq!(per_pool_cache.len(), 2);
+
+        let mut global_cache = TickWordCache::new(TickCacheBound::Global(1));
+        global_cache.insert(&pool_a, 0, word());
+        global_cache.insert(&pool_b, 0, word());
+        assert_eq!(global_cache.len(), 1);
+        assert!(global_cache.contains(pool_b.address, 0));
+    }
+
+    #[test]
+    fn test_global_bound_evicts_from_least_recently_used_pool() {
+        let pool_a = pool(1, 0);
+        let pool_b = pool(2, 0);
+
+        let mut cache = TickWordCache::new(TickCacheBound::Global(3));
+        cache.insert(&pool_a, 0, word());
+        cache.insert(&pool_a, 5, word());
+        cache.insert(&pool_b, 0, word());
+
+        //Pool A holds the least recently used word, so its word furthest from the current tick is evicted
+        cache.insert(&pool_b, 1, word());
+
+        assert_eq!(cache.len(), 3);
+        assert!(cache.contains(pool_a.address, 0));
+        assert!(!cache.contains(pool_a.address, 5));
+
+        cache.remove_pool(pool_b.address);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_words_are_invalidated_by_mint_and_burn_logs() {
+        let mut pool = pool(1, 0);
+        pool.tick_spacing = 60;
+        pool.last_synced_block = 100;
+
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(3));
+        cache.insert(&pool, -1, word());
+        cache.insert(&pool, 0, word());
+        cache.insert(&pool, 1, word());
+
+        //Resyncing the pool does not change the tick bitmap, so the words are still cached
+        pool.last_synced_block = 101;
+        pool.tick = 60;
+        assert!(cache.get(&pool, 0).is_some());
+
+        let tick_topic = |tick: i32| {
+            let mut topic = H256::zero();
+            I256::from(tick)
+                .into_raw()
+                .to_big_endian(topic.as_bytes_mut());
+            topic
+        };
+
+        let log = |signature: H256, tick_lower: i32, tick_upper: i32| Log {
+            address: pool.address,
+            topics: vec![
+                signature,
+                H256::zero(),
+                tick_topic(tick_lower),
+                tick_topic(tick_upper),
+            ],
+            ..Default::default()
+        };
+
+        assert!(!cache.update_from_log(&pool, &log(SWAP_EVENT_SIGNATURE, -60, 60)));
+        assert_eq!(cache.len(), 3);
+
+        //Ticks -60 and 15360 are in words -1 and 1
+        assert!(cache.update_from_log(&pool, &log(MINT_EVENT_SIGNATURE, -60, 15360)));
+        assert!(!cache.contains(pool.address, -1));
+        assert!(cache.contains(pool.address, 0));
+        assert!(!cache.contains(pool.address, 1));
+
+        assert!(cache.update_from_log(&pool, &log(BURN_EVENT_SIGNATURE, 0, 60)));
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simulate_swap_with_cache_fetches_missing_words() {
+        let pool = UniswapV3Pool {
+            address: H160::from_low_u64_be(1),
+            token_a: H160::from_low_u64_be(2),
+            token_b: H160::from_low_u64_be(3),
+            liquidity: 1000000000000000000,
+            sqrt_price: get_sqrt_ratio_at_tick(0).unwrap(),
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            last_synced_block: 100,
+            ..Default::default()
+        };
+        let v3_pool = abi::IUniswapV3Pool::new(pool.address, Arc::new(MockMiddleware::new(0)));
+
+        //No initialized ticks in word 0
+        let middleware = Arc::new(MockMiddleware::new(0).with_response(
+            pool.address,
+            v3_pool.tick_bitmap(0).calldata().unwrap(),
+            ethers::abi::encode(&[Token::Uint(U256::zero())]).into(),
+        ));
+
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(1));
+        let amount_in = U256::from(1000000);
+
+        let amount_out = pool
+            .simulate_swap_with_cache(pool.token_b, amount_in, &mut cache, middleware.clone())
+            .await
+            .unwrap();
+        assert_eq!(middleware.requests(), 1);
+        assert!(cache.contains(pool.address, 0));
+
+        //The second swap only uses the cached word
+        assert_eq!(
+            pool.simulate_swap_with_cache(pool.token_b, amount_in, &mut cache, middleware.clone())
+                .await
+                .unwrap(),
+            amount_out
+        );
+        assert_eq!(middleware.requests(), 1);
+
+        #[cfg(feature = "metrics")]
+        assert_eq!(
+            cache.metrics(),
+            TickCacheMetrics {
+                hits: 2,
+                misses: 1,
+                evictions: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_tick_word_batches_ticks() {
+        let pool = UniswapV3Pool {
+            address: H160::from_low_u64_be(1),
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        let v3_pool = abi::IUniswapV3Pool::new(pool.address, Arc::new(MockMiddleware::new(0)));
+
+        let tick_info = |liquidity_net: i128| -> Bytes {
+            ethers::abi::encode(&[
+                Token::Uint(U256::zero()),
+                Token::Int(I256::from(liquidity_net).into_raw()),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Int(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Bool(true),
+            ])
+            .into()
+        };
+
+        //Ticks 60 and 180 of word 0 are initialized
+        let bitmap = (U256::one() << 1) | (U256::one() << 3);
+        let middleware = Arc::new(
+            MockMiddleware::new(0)
+                .with_response(
+                    pool.address,
+                    v3_pool.tick_bitmap(0).calldata().unwrap(),
+                    ethers::abi::encode(&[Token::Uint(bitmap)]).into(),
+                )
+                .with_response(
+                    pool.address,
+                    v3_pool.ticks(60).calldata().unwrap(),
+                    tick_info(1000),
+                )
+                .with_response(
+                    pool.address,
+                    v3_pool.ticks(180).calldata().unwrap(),
+                    tick_info(-1000),
+                ),
+        );
+
+        let word = fetch_tick_word(&pool, 0, Some(U64::from(100)), middleware.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(word.bitmap, bitmap);
+        assert_eq!(
+            word.liquidity_net,
+            HashMap::from([(60, 1000), (180, -1000)])
+        );
+
+        //The bitmap and a single multicall for both ticks
+        assert_eq!(middleware.requests(), 2);
+    }
+
+    #[test]
+    fn test_simulate_swap_from_tick_cache() {
+        let pool = UniswapV3Pool {
+            address: H160::from_low_u64_be(1),
+            token_a: H160::from_low_u64_be(2),
+            token_b: H160::from_low_u64_be(3),
+            liquidity: 1000000000000000000,
+            sqrt_price: get_sqrt_ratio_at_tick(0).unwrap(),
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(1));
+        let amount_in = U256::from(1000000);
+
+        //The current word has not been fetched
+        assert!(matches!(
+            pool.simulate_swap_from_tick_cache(pool.token_b, amount_in, &mut cache),
+            Err(SwapSimulationError::TickDataOutOfRange {
+                word_position: 0,
+                ..
+            })
+        ));
+
+        //No initialized ticks in the word, the swap stays within the current tick range
+        cache.insert(&pool, 0, TickWord::default());
+        let amount_out = pool
+            .simulate_swap_from_tick_cache(pool.token_b, amount_in, &mut cache)
+            .unwrap();
+
+        assert!(amount_out > U256::zero() && amount_out < amount_in);
+
+        //Evicting the word results in an error rather than a quote from a partial map
+        cache.insert(&pool, 1, TickWord::default());
+        assert!(!cache.contains(pool.address, 0));
+        assert!(pool
+            .simulate_swap_from_tick_cache(pool.token_b, amount_in, &mut cache)
+            .is_err());
+    }
+
+    #[test]
+    fn test_tick_window_matches_tick_cache() {
+        let liquidity = 1000000000000000000_u128;
+        let mut pool = UniswapV3Pool {
+            address: H160::from_low_u64_be(1),
+            token_a: H160::from_low_u64_be(2),
+            token_b: H160::from_low_u64_be(3),
+            liquidity,
+            sqrt_price: get_sqrt_ratio_at_tick(0).unwrap(),
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        //Single initialized tick at 120, within word 0 of the bitmap which covers ticks 0 to 15300
+        let liquidity_net = (liquidity / 2) as i128;
+        pool.ticks.insert(
+            120,
+            TickInfo {
+                liquidity_net,
+                initialized: true,
+            },
+        );
+        pool.tick_window = Some(TickWindow {
+            num_ticks: 1,
+            lower_tick: 0,
+            upper_tick: 15300,
+        });
+
+        let mut cache = TickWordCache::new(TickCacheBound::PerPool(1));
+        cache.insert(
+            &pool,
+            0,
+            TickWord {
+                bitmap: U256::one() << 2,
+                liquidity_net: HashMap::from([(120, liquidity_net)]),
+            },
+        );
+
+        //Large enough to cross the initialized tick
+        let amount_in = U256::from(10000000000000000_u128);
+        let amount_out = pool.simulate_swap(pool.token_b, amount_in).unwrap();
+
+        assert_eq!(
+            amount_out,
+            pool.simulate_swap_from_tick_cache(pool.token_b, amount_in, &mut cache)
+                .unwrap()
+        );
+
+        //Swapping past the end of the window returns an error
+        assert!(matches!(
+            pool.simulate_swap(pool.token_b, U256::exp10(30)),
+            Err(SwapSimulationError::TickDataOutOfRange {
+                word_position: 1,
+                ..
+            })
+        ));
+
+        pool.tick_window = None;
+        assert!(pool.simulate_swap(pool.token_b, amount_in).is_err());
+    }
+
+    #[test]
+    fn test_compress_tick() {
+        assert_eq!(compress_tick(-1, 10), -1);
+        assert_eq!(compress_tick(-10, 10), -1);
+        assert_eq!(compress_tick(-11, 10), -2);
+        assert_eq!(compress_tick(15, 10), 1);
+    }
+}
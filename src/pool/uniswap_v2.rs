@@ -3,12 +3,12 @@ use std::sync::Arc;
 use ethers::{
     abi::{ethabi::Bytes, ParamType, Token},
     providers::Middleware,
-    types::{Log, H160, H256, U256},
+    types::{Log, H160, H256, U256, U64},
 };
 
 use crate::{
     abi, batch_requests,
-    errors::{ArithmeticError, CFMMError},
+    errors::{ArithmeticError, CFMMError, SwapSimulationError},
 };
 use serde::{Deserialize, Serialize};
 
@@ -19,6 +19,9 @@ pub const SYNC_EVENT_SIGNATURE: H256 = H256([
     199, 139, 229, 14, 6, 43, 3, 169, 255, 251, 186, 209,
 ]);
 
+//Fees are stored in thousandths of a percent, ex. 300 is a 0.3% (30 basis point) fee
+pub const POOL_FEE_DENOMINATOR: u64 = 100000;
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV2Pool {
     pub address: H160,
@@ -29,6 +32,8 @@ pub struct UniswapV2Pool {
     pub reserve_0: u128,
     pub reserve_1: u128,
     pub fee: u32,
+    #[serde(default)]
+    pub last_synced_block: u64,
 }
 
 impl UniswapV2Pool {
@@ -52,6 +57,7 @@ impl UniswapV2Pool {
             reserve_0,
             reserve_1,
             fee,
+            last_synced_block: 0,
         }
     }
 
@@ -69,6 +75,7 @@ impl UniswapV2Pool {
             reserve_0: 0,
             reserve_1: 0,
             fee: 300,
+            last_synced_block: 0,
         };
 
         pool.get_pool_data(middleware.clone()).await?;
@@ -103,6 +110,7 @@ impl UniswapV2Pool {
             reserve_0: 0,
             reserve_1: 0,
             fee: 300,
+            last_synced_block: 0,
         })
     }
 
@@ -130,11 +138,25 @@ impl UniswapV2Pool {
     pub async fn get_reserves<M: Middleware>(
         &self,
         middleware: Arc<M>,
+    ) -> Result<(u128, u128), CFMMError<M>> {
+        self.get_reserves_at_block(None, middleware).await
+    }
+
+    pub async fn get_reserves_at_block<M: Middleware>(
+        &self,
+        block_number: Option<U64>,
+        middleware: Arc<M>,
     ) -> Result<(u128, u128), CFMMError<M>> {
         //Initialize a new instance of the Pool
         let v2_pair = abi::IUniswapV2Pair::new(self.address, middleware);
+
+        let mut get_reserves = v2_pair.get_reserves();
+        if let Some(block_number) = block_number {
+            get_reserves = get_reserves.block(block_number);
+        }
+
         // Make a call to get the reserves
-        let (reserve_0, reserve_1, _) = match v2_pair.get_reserves().call().await {
+        let (reserve_0, reserve_1, _) = match get_reserves.call().await {
             Ok(result) => result,
             Err(contract_error) => return Err(CFMMError::ContractError(contract_error)),
         };
@@ -142,11 +164,27 @@ impl UniswapV2Pool {
         Ok((reserve_0, reserve_1))
     }
 
+    //Syncs the reserves at the latest block. This does not update `last_synced_block`,
+    //use `sync_pool_at_block` to record the block that the state was read at.
     pub async fn sync_pool<M: Middleware>(
         &mut self,
         middleware: Arc<M>,
     ) -> Result<(), CFMMError<M>> {
-        (self.reserve_0, self.reserve_1) = self.get_reserves(middleware).await?;
+        (self.reserve_0, self.reserve_1) = self.get_reserves_at_block(None, middleware).await?;
+
+        Ok(())
+    }
+
+    //Syncs the reserves at `block_number` and records it as the last synced block
+    pub async fn sync_pool_at_block<M: Middleware>(
+        &mut self,
+        block_number: U64,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        (self.reserve_0, self.reserve_1) = self
+            .get_reserves_at_block(Some(block_number), middleware)
+            .await?;
+        self.last_synced_block = block_number.as_u64();
 
         Ok(())
     }
@@ -224,7 +262,7 @@ impl UniswapV2Pool {
         if base_token == self.token_a {
             Ok(fixed_point_math::div_uu(r_1, r_0)?)
         } else {
-            Ok(fixed_point_math::div_uu(r_0, r_1))?
+            Ok(fixed_point_math::div_uu(r_0, r_1)?)
         }
     }
 
@@ -261,58 +299,75 @@ impl UniswapV2Pool {
         )
     }
 
-    pub fn simulate_swap(&self, token_in: H160, amount_in: U256) -> U256 {
+    //Returns the amount of the other token received for `amount_in` of `token_in`, both in their native decimals
+    pub fn simulate_swap(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, SwapSimulationError> {
         if self.token_a == token_in {
             self.get_amount_out(
                 amount_in,
                 U256::from(self.reserve_0),
                 U256::from(self.reserve_1),
             )
-        } else {
+        } else if self.token_b == token_in {
             self.get_amount_out(
                 amount_in,
                 U256::from(self.reserve_1),
                 U256::from(self.reserve_0),
             )
+        } else {
+            Err(SwapSimulationError::TokenNotInPool {
+                pool: self.address,
+                token: token_in,
+            })
         }
     }
 
-    pub fn simulate_swap_mut(&mut self, token_in: H160, amount_in: U256) -> U256 {
-        if self.token_a == token_in {
-            let amount_out = self.get_amount_out(
-                amount_in,
-                U256::from(self.reserve_0),
-                U256::from(self.reserve_1),
-            );
+    pub fn simulate_swap_mut(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, SwapSimulationError> {
+        let amount_out = self.simulate_swap(token_in, amount_in)?;
 
+        if self.token_a == token_in {
             self.reserve_0 += amount_in.as_u128();
             self.reserve_1 -= amount_out.as_u128();
-
-            amount_out
         } else {
-            let amount_out = self.get_amount_out(
-                amount_in,
-                U256::from(self.reserve_1),
-                U256::from(self.reserve_0),
-            );
-
             self.reserve_0 -= amount_out.as_u128();
             self.reserve_1 += amount_in.as_u128();
-
-            amount_out
         }
+
+        Ok(amount_out)
     }
 
-    pub fn get_amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+    //Constant product amount out, taking the pool fee from the amount in.
+    //Returns an error if the pool fee is greater than `POOL_FEE_DENOMINATOR`.
+    pub fn get_amount_out(
+        &self,
+        amount_in: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Result<U256, SwapSimulationError> {
+        let fee_denominator = U256::from(POOL_FEE_DENOMINATOR);
+        let fee_multiplier = fee_denominator.checked_sub(U256::from(self.fee)).ok_or(
+            SwapSimulationError::InvalidFee {
+                pool: self.address,
+                fee: self.fee,
+            },
+        )?;
+
         if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return U256::zero();
+            return Ok(U256::zero());
         }
 
-        let amount_in_with_fee = amount_in * U256::from(997);
+        let amount_in_with_fee = amount_in * fee_multiplier;
         let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * U256::from(1000) + amount_in_with_fee;
+        let denominator = reserve_in * fee_denominator + amount_in_with_fee;
 
-        numerator / denominator
+        Ok(numerator / denominator)
     }
 
     pub fn swap_calldata(
@@ -347,6 +402,7 @@ mod tests {
     };
 
     use super::UniswapV2Pool;
+    use crate::{errors::SwapSimulationError, test_utils::usdc_weth_pool};
 
     #[test]
     fn test_swap_calldata() {
@@ -360,6 +416,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_simulate_swap() {
+        let mut pool = usdc_weth_pool(0);
+
+        //1000 USDC for WETH
+        assert_eq!(
+            pool.simulate_swap(pool.token_a, U256::from(1000000000))
+                .unwrap(),
+            U256::from(601179068433854669_u128)
+        );
+
+        //1 WETH for USDC
+        assert_eq!(
+            pool.simulate_swap(pool.token_b, U256::exp10(18)).unwrap(),
+            U256::from(1653339430)
+        );
+
+        assert_eq!(
+            pool.simulate_swap(pool.token_a, U256::zero()).unwrap(),
+            U256::zero()
+        );
+
+        //0.25% fee
+        pool.fee = 250;
+        assert_eq!(
+            pool.simulate_swap(pool.token_a, U256::from(1000000000))
+                .unwrap(),
+            U256::from(601480556064046085_u128)
+        );
+    }
+
+    #[test]
+    fn test_simulate_swap_token_not_in_pool() {
+        let mut pool = usdc_weth_pool(0);
+
+        assert!(matches!(
+            pool.simulate_swap(H160::zero(), U256::from(1000000000)),
+            Err(SwapSimulationError::TokenNotInPool { .. })
+        ));
+
+        assert!(pool
+            .simulate_swap_mut(H160::zero(), U256::from(1000000000))
+            .is_err());
+        assert_eq!(pool, usdc_weth_pool(0));
+    }
+
+    #[test]
+    fn test_simulate_swap_invalid_fee() {
+        let mut pool = usdc_weth_pool(0);
+        pool.fee = 100001;
+
+        assert!(matches!(
+            pool.simulate_swap(pool.token_a, U256::from(1000000000)),
+            Err(SwapSimulationError::InvalidFee { fee: 100001, .. })
+        ));
+
+        assert!(pool
+            .simulate_swap_mut(pool.token_a, U256::from(1000000000))
+            .is_err());
+        pool.fee = 300;
+        assert_eq!(pool, usdc_weth_pool(0));
+    }
+
+    #[test]
+    fn test_simulate_swap_mut() {
+        let mut pool = usdc_weth_pool(0);
+
+        let amount_out = pool
+            .simulate_swap_mut(pool.token_a, U256::from(1000000000))
+            .unwrap();
+
+        assert_eq!(pool.reserve_0, 47092140895915 + 1000000000);
+        assert_eq!(
+            pool.reserve_1,
+            28396598565590008529300 - amount_out.as_u128()
+        );
+    }
+
     #[tokio::test]
     async fn test_get_new_from_address() {
         let rpc_endpoint = std::env::var("ETHEREUM_MAINNET_ENDPOINT")
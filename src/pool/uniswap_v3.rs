@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use ethers::{
     abi::{decode, ethabi::Bytes, ParamType, Token},
@@ -9,8 +9,11 @@ use num_bigfloat::BigFloat;
 
 use crate::{
     abi, batch_requests,
-    errors::{ArithmeticError, CFMMError},
+    errors::{ArithmeticError, CFMMError, SwapSimulationError},
+    math,
 };
+
+use super::tick_cache::{self, TickWordCache};
 use serde::{Deserialize, Serialize};
 
 pub const MIN_SQRT_RATIO: U256 = U256([4295128739, 0, 0, 0]);
@@ -19,11 +22,19 @@ pub const SWAP_EVENT_SIGNATURE: H256 = H256([
     196, 32, 121, 249, 74, 99, 80, 215, 230, 35, 95, 41, 23, 73, 36, 249, 40, 204, 42, 200, 24,
     235, 100, 254, 216, 0, 78, 17, 95, 188, 202, 103,
 ]);
+pub const MINT_EVENT_SIGNATURE: H256 = H256([
+    122, 83, 8, 11, 164, 20, 21, 139, 231, 236, 105, 185, 135, 181, 251, 125, 7, 222, 225, 1, 254,
+    133, 72, 143, 8, 83, 174, 22, 35, 157, 11, 222,
+]);
+pub const BURN_EVENT_SIGNATURE: H256 = H256([
+    12, 57, 108, 217, 137, 163, 159, 68, 89, 181, 250, 26, 237, 106, 154, 141, 205, 188, 69, 144,
+    138, 207, 214, 126, 2, 140, 213, 104, 218, 152, 152, 44,
+]);
 
 pub const U256_TWO: U256 = U256([2, 0, 0, 0]);
 pub const Q128: U256 = U256([0, 0, 1, 0]);
 pub const Q224: U256 = U256([0, 0, 0, 4294967296]);
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct UniswapV3Pool {
     pub address: H160,
     pub token_a: H160,
@@ -36,6 +47,28 @@ pub struct UniswapV3Pool {
     pub tick: i32,
     pub tick_spacing: i32,
     pub liquidity_net: i128,
+    #[serde(default)]
+    pub last_synced_block: u64,
+    //Initialized ticks within the synced tick window, used to simulate swaps locally
+    #[serde(default)]
+    pub ticks: BTreeMap<i32, TickInfo>,
+    #[serde(default)]
+    pub tick_window: Option<TickWindow>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct TickInfo {
+    pub liquidity_net: i128,
+    pub initialized: bool,
+}
+
+//Range of ticks that `ticks` holds every initialized tick for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct TickWindow {
+    //Number of ticks synced in each direction from the current tick
+    pub num_ticks: u16,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
 }
 
 impl UniswapV3Pool {
@@ -65,6 +98,9 @@ impl UniswapV3Pool {
             tick,
             tick_spacing,
             liquidity_net,
+            last_synced_block: 0,
+            ticks: BTreeMap::new(),
+            tick_window: None,
         }
     }
 
@@ -85,6 +121,9 @@ impl UniswapV3Pool {
             tick_spacing: 0,
             fee: 0,
             liquidity_net: 0,
+            last_synced_block: 0,
+            ticks: BTreeMap::new(),
+            tick_window: None,
         };
 
         pool.get_pool_data(middleware.clone()).await?;
@@ -124,6 +163,9 @@ impl UniswapV3Pool {
             tick_spacing: 0,
             tick: 0,
             liquidity_net: 0,
+            last_synced_block: 0,
+            ticks: BTreeMap::new(),
+            tick_window: None,
         })
     }
 
@@ -238,11 +280,48 @@ impl UniswapV3Pool {
         Ok(self.get_slot_0(middleware).await?.0)
     }
 
+    //Syncs slot0 and liquidity at the latest block. This does not update `last_synced_block`,
+    //use `sync_pool_at_block` to record the block that the state was read at.
+    //Pools with a tick window are synced at the latest block number so that the ticks match slot0 and liquidity.
     pub async fn sync_pool<M: Middleware>(
         &mut self,
         middleware: Arc<M>,
     ) -> Result<(), CFMMError<M>> {
-        batch_requests::uniswap_v3::sync_v3_pool_batch_request(self, middleware.clone()).await?;
+        if self.tick_window.is_some() {
+            let block_number = middleware
+                .get_block_number()
+                .await
+                .map_err(CFMMError::MiddlewareError)?;
+
+            return self.sync_pool_at_block(block_number, middleware).await;
+        }
+
+        batch_requests::uniswap_v3::sync_v3_pool_batch_request(self, None, middleware).await?;
+
+        Ok(())
+    }
+
+    //Syncs slot0, liquidity and the tick window if synced, at `block_number` and records it as the last synced block
+    pub async fn sync_pool_at_block<M: Middleware>(
+        &mut self,
+        block_number: U64,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        batch_requests::uniswap_v3::sync_v3_pool_batch_request(
+            self,
+            Some(block_number),
+            middleware.clone(),
+        )
+        .await?;
+
+        //Refresh the tick window around the new current tick if the pool is simulated locally
+        if let Some(tick_window) = self.tick_window {
+            self.sync_ticks(tick_window.num_ticks, Some(block_number), middleware)
+                .await?;
+        }
+
+        self.last_synced_block = block_number.as_u64();
+
         Ok(())
     }
 
@@ -344,15 +423,13 @@ impl UniswapV3Pool {
        ==> y = L^2*price
     */
     pub fn calculate_virtual_reserves(&self) -> Result<(u128, u128), ArithmeticError> {
-        let price: f64 = self.calculate_price(self.token_a);
+        let price: f64 = self.calculate_price(self.token_a)?;
 
         let sqrt_price = BigFloat::from_f64(price.sqrt());
         let liquidity = BigFloat::from_u128(self.liquidity);
 
         //Sqrt price is stored as a Q64.96 so we need to left shift the liquidity by 96 to be represented as Q64.96
         //We cant right shift sqrt_price because it could move the value to 0, making divison by 0 to get reserve_x
-        let liquidity = liquidity;
-
         let (reserve_0, reserve_1) = if !sqrt_price.is_zero() {
             let reserve_x = liquidity.div(&sqrt_price);
             let reserve_y = liquidity.mul(&sqrt_price);
@@ -372,19 +449,14 @@ impl UniswapV3Pool {
         ))
     }
 
-    pub fn calculate_price(&self, base_token: H160) -> f64 {
-        let tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(self.sqrt_price).unwrap();
-        let shift = self.token_a_decimals as i8 - self.token_b_decimals as i8;
-        let price = if shift < 0 {
-            1.0001_f64.powi(tick) / 10_f64.powi(-shift as i32)
-        } else {
-            1.0001_f64.powi(tick) * 10_f64.powi(shift as i32)
-        };
+    pub fn calculate_price(&self, base_token: H160) -> Result<f64, ArithmeticError> {
+        let tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(self.sqrt_price)?;
+        let price = math::tick_to_price(tick, self.token_a_decimals, self.token_b_decimals)?;
 
         if base_token == self.token_a {
-            price
+            Ok(price)
         } else {
-            1.0 / price
+            Ok(1.0 / price)
         }
     }
 
@@ -392,161 +464,112 @@ impl UniswapV3Pool {
         self.address
     }
 
+    //Simulates the swap using the cached tick words, fetching any words that are not cached, and updates the pool state.
+    //Cached words stay valid after the swap as it does not change the tick bitmap or liquidity net.
     pub async fn simulate_swap_mut_with_cache<M: Middleware>(
         &mut self,
         token_in: H160,
         amount_in: U256,
-        num_ticks: u16,
+        tick_cache: &mut TickWordCache,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
-        }
+        let (current_state, liquidity_net) = self
+            .simulate_swap_state_with_cache(token_in, amount_in, tick_cache, middleware)
+            .await?;
 
-        let zero_for_one = token_in == self.token_a;
+        //Update the pool state
+        self.liquidity = current_state.liquidity;
+        self.sqrt_price = current_state.sqrt_price_x_96;
+        self.tick = current_state.tick;
+        self.liquidity_net = liquidity_net;
 
-        //TODO: make this a queue instead of vec and then an iterator FIXME::
-        let (mut tick_data, block_number) =
-            batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
-                self,
-                self.tick,
-                zero_for_one,
-                num_ticks,
-                None,
-                middleware.clone(),
-            )
+        Ok((-current_state.amount_calculated).into_raw())
+    }
+
+    //Simulates the swap using the cached tick words, fetching any words that are not cached
+    pub async fn simulate_swap_with_cache<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        tick_cache: &mut TickWordCache,
+        middleware: Arc<M>,
+    ) -> Result<U256, CFMMError<M>> {
+        let (current_state, _) = self
+            .simulate_swap_state_with_cache(token_in, amount_in, tick_cache, middleware)
             .await?;
 
-        let mut tick_data_iter = tick_data.iter();
+        Ok((-current_state.amount_calculated).into_raw())
+    }
 
-        //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + 1
+    //Words are fetched at the block the pool was last synced at so that they match the pool state
+    async fn simulate_swap_state_with_cache<M: Middleware>(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        tick_cache: &mut TickWordCache,
+        middleware: Arc<M>,
+    ) -> Result<(CurrentState, i128), CFMMError<M>> {
+        let block_number = if self.last_synced_block > 0 {
+            Some(U64::from(self.last_synced_block))
         } else {
-            MAX_SQRT_RATIO - 1
+            None
         };
 
-        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
-        };
-
-        let mut liquidity_net = self.liquidity_net;
-
-        while current_state.amount_specified_remaining != I256::zero()
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                ..Default::default()
-            };
+        let mut words_fetched = 0;
+
+        loop {
+            match self.simulate_swap_state_from_tick_cache(token_in, amount_in, tick_cache) {
+                Err(SwapSimulationError::TickDataOutOfRange {
+                    pool,
+                    word_position,
+                }) => {
+                    //If the swap needs more words than the cache can hold, fetched words would evict each other indefinitely
+                    if words_fetched > tick_cache.bound().max_words() {
+                        return Err(SwapSimulationError::TickDataOutOfRange {
+                            pool,
+                            word_position,
+                        }
+                        .into());
+                    }
 
-            let next_tick_data = if let Some(tick_data) = tick_data_iter.next() {
-                tick_data
-            } else {
-                (tick_data, _) =
-                    batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
+                    let word = tick_cache::fetch_tick_word(
                         self,
-                        current_state.tick,
-                        zero_for_one,
-                        num_ticks,
-                        Some(block_number),
+                        word_position,
+                        block_number,
                         middleware.clone(),
                     )
                     .await?;
 
-                tick_data_iter = tick_data.iter();
-
-                if let Some(tick_data) = tick_data_iter.next() {
-                    tick_data
-                } else {
-                    //This should never happen, but if it does, we should return an error because something is wrong
-                    return Err(CFMMError::NoInitializedTicks);
+                    tick_cache.insert(self, word_position, word);
+                    words_fetched += 1;
                 }
-            };
-
-            step.tick_next = next_tick_data.tick;
-
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
-
-            //Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
-
-            //Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
-
-            //Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
-
-            //Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
 
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
+                result => return Ok(result?),
+            }
+        }
+    }
 
-            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if next_tick_data.initialized {
-                    liquidity_net = next_tick_data.liquidity_net;
+    //Simulates the swap using the synced tick window without making any calls.
+    //Returns `TickDataOutOfRange` if the swap would cross a tick outside of the window, see `sync_ticks`.
+    pub fn simulate_swap(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, SwapSimulationError> {
+        let (current_state, _) = self.simulate_swap_state_from_tick_window(token_in, amount_in)?;
 
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
+        Ok((-current_state.amount_calculated).into_raw())
+    }
 
-                    current_state.liquidity = if liquidity_net < 0 {
-                        current_state.liquidity - (-liquidity_net as u128)
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                //Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
-                }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
-            }
-        }
+    //Simulates the swap using the synced tick window and updates the pool state.
+    //The tick window stays valid after the swap as it does not change the tick bitmap or liquidity net.
+    pub fn simulate_swap_mut(
+        &mut self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<U256, SwapSimulationError> {
+        let (current_state, liquidity_net) =
+            self.simulate_swap_state_from_tick_window(token_in, amount_in)?;
 
         //Update the pool state
         self.liquidity = current_state.liquidity;
@@ -557,33 +580,217 @@ impl UniswapV3Pool {
         Ok((-current_state.amount_calculated).into_raw())
     }
 
-    pub async fn simulate_swap_with_cache<M: Middleware>(
+    fn simulate_swap_state_from_tick_window(
         &self,
         token_in: H160,
         amount_in: U256,
-        num_ticks: u16,
-        middleware: Arc<M>,
-    ) -> Result<U256, CFMMError<M>> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
-        }
-
+    ) -> Result<(CurrentState, i128), SwapSimulationError> {
         let zero_for_one = token_in == self.token_a;
 
-        //TODO: make this a queue instead of vec and then an iterator FIXME::
-        let (mut tick_data, block_number) =
+        self.simulate_swap_with_tick_source(zero_for_one, amount_in, |tick, lte| {
+            self.next_initialized_tick_from_tick_window(tick, lte)
+        })
+    }
+
+    //Gets the next tick within one word of the tick bitmap from the synced ticks, mirroring `nextInitializedTickWithinOneWord`
+    fn next_initialized_tick_from_tick_window(
+        &self,
+        tick: i32,
+        lte: bool,
+    ) -> Result<(i32, bool, i128), SwapSimulationError> {
+        let compressed = tick_cache::compress_tick(tick, self.tick_spacing);
+
+        let (word_position, bit_position) = if lte {
+            uniswap_v3_math::tick_bit_map::position(compressed)
+        } else {
+            uniswap_v3_math::tick_bit_map::position(compressed + 1)
+        };
+
+        let out_of_range = SwapSimulationError::TickDataOutOfRange {
+            pool: self.address,
+            word_position,
+        };
+
+        let tick_window = self.tick_window.ok_or(out_of_range)?;
+
+        //Compressed ticks to search, ordered in the direction of the swap
+        let (search_start, search_end) = if lte {
+            (compressed, compressed - bit_position as i32)
+        } else {
+            (compressed + 1, compressed + 1 + (255 - bit_position) as i32)
+        };
+
+        let mut compressed_tick = search_start;
+        loop {
+            let tick = compressed_tick * self.tick_spacing;
+
+            //Every initialized tick within the window is synced, so ticks outside of it can not be trusted
+            if tick < tick_window.lower_tick.max(MIN_TICK)
+                || tick > tick_window.upper_tick.min(MAX_TICK)
+            {
+                //The word boundary is past the min or max tick, so there are no more ticks to cross
+                if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+                    return Ok((search_end * self.tick_spacing, false, 0));
+                }
+
+                //Stop at the last tick within the window, the swap only needs the ticks past it if it continues from there
+                if compressed_tick != search_start {
+                    let last_tick = compressed_tick + if lte { 1 } else { -1 };
+                    return Ok((last_tick * self.tick_spacing, false, 0));
+                }
+
+                return Err(SwapSimulationError::TickDataOutOfRange {
+                    pool: self.address,
+                    word_position,
+                });
+            }
+
+            if let Some(tick_info) = self.ticks.get(&tick) {
+                if tick_info.initialized {
+                    return Ok((tick, true, tick_info.liquidity_net));
+                }
+            }
+
+            if compressed_tick == search_end {
+                return Ok((tick, false, 0));
+            }
+
+            compressed_tick += if lte { -1 } else { 1 };
+        }
+    }
+
+    //Syncs every initialized tick within `num_ticks` ticks of the bitmap in each direction from the current tick
+    pub async fn sync_ticks<M: Middleware>(
+        &mut self,
+        num_ticks: u16,
+        block_number: Option<U64>,
+        middleware: Arc<M>,
+    ) -> Result<(), CFMMError<M>> {
+        let (lower_tick_data, block_number) =
             batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
                 self,
                 self.tick,
-                zero_for_one,
+                true,
                 num_ticks,
-                None,
+                block_number,
                 middleware.clone(),
             )
             .await?;
 
-        let mut tick_data_iter = tick_data.iter();
+        let (upper_tick_data, _) =
+            batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
+                self,
+                self.tick,
+                false,
+                num_ticks,
+                Some(block_number),
+                middleware,
+            )
+            .await?;
+
+        let mut ticks = BTreeMap::new();
+        for tick_data in lower_tick_data.iter().chain(upper_tick_data.iter()) {
+            if tick_data.initialized {
+                ticks.insert(
+                    tick_data.tick,
+                    TickInfo {
+                        liquidity_net: tick_data.liquidity_net,
+                        initialized: true,
+                    },
+                );
+            }
+        }
+
+        //The tick data is returned in order moving away from the current tick, so the last tick in each direction bounds the window
+        self.tick_window = Some(TickWindow {
+            num_ticks,
+            lower_tick: lower_tick_data
+                .last()
+                .map(|tick_data| tick_data.tick)
+                .unwrap_or(self.tick),
+            upper_tick: upper_tick_data
+                .last()
+                .map(|tick_data| tick_data.tick)
+                .unwrap_or(self.tick),
+        });
+        self.ticks = ticks;
+
+        Ok(())
+    }
+
+    //Simulates the swap using only the cached tick words, returning `TickDataOutOfRange` if the swap needs a word that is not cached
+    pub fn simulate_swap_from_tick_cache(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        tick_cache: &mut TickWordCache,
+    ) -> Result<U256, SwapSimulationError> {
+        let (current_state, _) =
+            self.simulate_swap_state_from_tick_cache(token_in, amount_in, tick_cache)?;
+
+        Ok((-current_state.amount_calculated).into_raw())
+    }
+
+    fn simulate_swap_state_from_tick_cache(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        tick_cache: &mut TickWordCache,
+    ) -> Result<(CurrentState, i128), SwapSimulationError> {
+        let zero_for_one = token_in == self.token_a;
 
+        self.simulate_swap_with_tick_source(zero_for_one, amount_in, |tick, lte| {
+            let compressed = tick_cache::compress_tick(tick, self.tick_spacing);
+            let (word_position, bit_position) = if lte {
+                uniswap_v3_math::tick_bit_map::position(compressed)
+            } else {
+                uniswap_v3_math::tick_bit_map::position(compressed + 1)
+            };
+
+            let out_of_range = SwapSimulationError::TickDataOutOfRange {
+                pool: self.address,
+                word_position,
+            };
+
+            let word = tick_cache.get(self, word_position).ok_or(out_of_range)?;
+
+            let (tick_next, initialized) =
+                uniswap_v3_math::tick_bit_map::next_initialized_tick_within_one_word(
+                    self.tick_spacing,
+                    lte,
+                    compressed,
+                    bit_position,
+                    word.bitmap,
+                )?;
+
+            let liquidity_net = if initialized {
+                //The bit is set so the liquidity net must have been fetched with the word
+                *word.liquidity_net.get(&tick_next).ok_or(
+                    SwapSimulationError::TickDataOutOfRange {
+                        pool: self.address,
+                        word_position,
+                    },
+                )?
+            } else {
+                0
+            };
+
+            Ok((tick_next, initialized, liquidity_net))
+        })
+    }
+
+    //Simulates the swap, getting the next tick, whether it is initialized and its liquidity net from `next_tick`.
+    //`next_tick` is called with the current tick and whether to search less than or equal to the tick.
+    //Returns the state of the pool after the swap and the liquidity net of the last crossed tick.
+    fn simulate_swap_with_tick_source<F>(
+        &self,
+        zero_for_one: bool,
+        amount_in: U256,
+        mut next_tick: F,
+    ) -> Result<(CurrentState, i128), SwapSimulationError>
+    where
+        F: FnMut(i32, bool) -> Result<(i32, bool, i128), SwapSimulationError>,
+    {
         //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
         let sqrt_price_limit_x_96 = if zero_for_one {
             MIN_SQRT_RATIO + 1
@@ -593,53 +800,30 @@ impl UniswapV3Pool {
 
         //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
         let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
+            sqrt_price_x_96: self.sqrt_price,
+            amount_calculated: I256::zero(),
+            amount_specified_remaining: I256::from_raw(amount_in),
+            tick: self.tick,
+            liquidity: self.liquidity,
         };
 
+        let mut last_liquidity_net = self.liquidity_net;
+
         while current_state.amount_specified_remaining != I256::zero()
             && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
         {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
             let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
+                sqrt_price_start_x_96: current_state.sqrt_price_x_96,
                 ..Default::default()
             };
 
-            let next_tick_data = if let Some(tick_data) = tick_data_iter.next() {
-                tick_data
-            } else {
-                (tick_data, _) =
-                    batch_requests::uniswap_v3::get_uniswap_v3_tick_data_batch_request(
-                        self,
-                        current_state.tick,
-                        zero_for_one,
-                        num_ticks,
-                        Some(block_number),
-                        middleware.clone(),
-                    )
-                    .await?;
-
-                tick_data_iter = tick_data.iter();
-
-                if let Some(tick_data) = tick_data_iter.next() {
-                    tick_data
-                } else {
-                    //This should never happen, but if it does, we should return an error because something is wrong
-                    return Err(CFMMError::NoInitializedTicks);
-                }
-            };
-
-            step.tick_next = next_tick_data.tick;
+            let mut liquidity_net;
+            (step.tick_next, step.initialized, liquidity_net) =
+                next_tick(current_state.tick, zero_for_one)?;
 
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
+            //Ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
             step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
 
-            //Get the next sqrt price from the input amount
             step.sqrt_price_next_x96 =
                 uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
 
@@ -682,13 +866,11 @@ impl UniswapV3Pool {
 
             //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
             if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if next_tick_data.initialized {
-                    let mut liquidity_net = next_tick_data.liquidity_net;
-
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
+                if step.initialized {
                     if zero_for_one {
                         liquidity_net = -liquidity_net;
                     }
+                    last_liquidity_net = liquidity_net;
 
                     current_state.liquidity = if liquidity_net < 0 {
                         current_state.liquidity - (-liquidity_net as u128)
@@ -696,14 +878,12 @@ impl UniswapV3Pool {
                         current_state.liquidity + (liquidity_net as u128)
                     };
                 }
-                //Increment the current tick
+
                 current_state.tick = if zero_for_one {
                     step.tick_next.wrapping_sub(1)
                 } else {
                     step.tick_next
                 }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
             } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
                 current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
                     current_state.sqrt_price_x_96,
@@ -711,17 +891,7 @@ impl UniswapV3Pool {
             }
         }
 
-        Ok((-current_state.amount_calculated).into_raw())
-    }
-
-    pub async fn simulate_swap<M: Middleware>(
-        &self,
-        token_in: H160,
-        amount_in: U256,
-        middleware: Arc<M>,
-    ) -> Result<U256, CFMMError<M>> {
-        self.simulate_swap_with_cache(token_in, amount_in, 150, middleware)
-            .await
+        Ok((current_state, last_liquidity_net))
     }
 
     pub async fn get_word<M: Middleware>(
@@ -730,12 +900,12 @@ impl UniswapV3Pool {
         block_number: Option<U64>,
         middleware: Arc<M>,
     ) -> Result<U256, CFMMError<M>> {
-        if block_number.is_some() {
+        if let Some(block_number) = block_number {
             //TODO: in the future, create a batch call to get this and liquidity net within the same call
 
             Ok(abi::IUniswapV3Pool::new(self.address, middleware.clone())
                 .tick_bitmap(word_pos)
-                .block(block_number.unwrap())
+                .block(block_number)
                 .call()
                 .await?)
         } else {
@@ -759,16 +929,6 @@ impl UniswapV3Pool {
         uniswap_v3_math::tick_bit_map::position(compressed)
     }
 
-    pub async fn simulate_swap_mut<M: Middleware>(
-        &mut self,
-        token_in: H160,
-        amount_in: U256,
-        middleware: Arc<M>,
-    ) -> Result<U256, CFMMError<M>> {
-        self.simulate_swap_mut_with_cache(token_in, amount_in, 150, middleware)
-            .await
-    }
-
     pub fn swap_calldata(
         &self,
         recipient: H160,
@@ -831,7 +991,12 @@ mod test {
     use crate::abi::IUniswapV3Pool;
 
     #[allow(unused)]
-    use super::UniswapV3Pool;
+    use super::{TickInfo, TickWindow, UniswapV3Pool};
+    #[allow(unused)]
+    use crate::{
+        errors::SwapSimulationError,
+        pool::tick_cache::{TickCacheBound, TickWordCache},
+    };
     #[allow(unused)]
     use ethers::providers::Middleware;
 
@@ -845,6 +1010,8 @@ mod test {
     use std::error::Error;
     #[allow(unused)]
     use std::{str::FromStr, sync::Arc};
+    #[allow(unused)]
+    use uniswap_v3_math::tick_math::{get_sqrt_ratio_at_tick, MAX_TICK, MIN_TICK};
 
     abigen!(
         IQuoter,
@@ -873,8 +1040,9 @@ mod test {
         let amount_in = U256::from_dec_str("100000000").unwrap(); // 100 USDC
 
         let current_block = middleware.get_block_number().await.unwrap();
+        let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
         let amount_out = pool
-            .simulate_swap(pool.token_a, amount_in, middleware.clone())
+            .simulate_swap_with_cache(pool.token_a, amount_in, &mut tick_cache, middleware.clone())
             .await
             .unwrap();
 
@@ -915,8 +1083,14 @@ mod test {
         let amount_in_1 = U256::from_dec_str("10000000000").unwrap(); // 10_000 USDC
 
         let current_block = middleware.get_block_number().await.unwrap();
+        let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
         let amount_out_1 = pool
-            .simulate_swap(pool.token_a, amount_in_1, middleware.clone())
+            .simulate_swap_with_cache(
+                pool.token_a,
+                amount_in_1,
+                &mut tick_cache,
+                middleware.clone(),
+            )
             .await
             .unwrap();
 
@@ -957,8 +1131,14 @@ mod test {
         let amount_in_2 = U256::from_dec_str("10000000000000").unwrap(); // 10_000_000 USDC
 
         let current_block = middleware.get_block_number().await.unwrap();
+        let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
         let amount_out_2 = pool
-            .simulate_swap(pool.token_a, amount_in_2, middleware.clone())
+            .simulate_swap_with_cache(
+                pool.token_a,
+                amount_in_2,
+                &mut tick_cache,
+                middleware.clone(),
+            )
             .await
             .unwrap();
 
@@ -1002,8 +1182,14 @@ mod test {
         dbg!(pool.tick_spacing);
 
         let current_block = middleware.get_block_number().await.unwrap();
+        let mut tick_cache = TickWordCache::new(TickCacheBound::PerPool(usize::MAX));
         let amount_out_3 = pool
-            .simulate_swap(pool.token_a, amount_in_3, middleware.clone())
+            .simulate_swap_with_cache(
+                pool.token_a,
+                amount_in_3,
+                &mut tick_cache,
+                middleware.clone(),
+            )
             .await
             .unwrap();
 
@@ -1170,13 +1356,115 @@ mod test {
         let sqrt_price = block_pool.slot_0().block(16515398).call().await.unwrap().0;
         pool.sqrt_price = sqrt_price;
 
-        let float_price_a = pool.calculate_price(pool.token_a);
+        let float_price_a = pool.calculate_price(pool.token_a).unwrap();
 
-        let float_price_b = pool.calculate_price(pool.token_b);
+        let float_price_b = pool.calculate_price(pool.token_b).unwrap();
 
         dbg!(pool);
 
         println!("Price A: {float_price_a}");
         println!("Price B: {float_price_b}");
     }
+
+    #[allow(unused)]
+    fn pool_with_tick_window(tick: i32, lower_tick: i32, upper_tick: i32) -> UniswapV3Pool {
+        UniswapV3Pool {
+            address: H160::from_low_u64_be(1),
+            token_a: H160::from_low_u64_be(2),
+            token_b: H160::from_low_u64_be(3),
+            liquidity: 1000000000000000000,
+            sqrt_price: get_sqrt_ratio_at_tick(tick).unwrap(),
+            fee: 3000,
+            tick,
+            tick_spacing: 60,
+            tick_window: Some(TickWindow {
+                num_ticks: 10,
+                lower_tick,
+                upper_tick,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap_out_of_tick_window() {
+        let pool = pool_with_tick_window(0, -600, 600);
+
+        //Small swaps stay within the window
+        assert!(pool
+            .simulate_swap(pool.token_a, U256::from(1000000))
+            .is_ok());
+        assert!(pool
+            .simulate_swap(pool.token_b, U256::from(1000000))
+            .is_ok());
+
+        //Swapping down past tick -600 needs word -1, which is only partially synced
+        assert!(matches!(
+            pool.simulate_swap(pool.token_a, U256::exp10(30)),
+            Err(SwapSimulationError::TickDataOutOfRange {
+                word_position: -1,
+                ..
+            })
+        ));
+
+        //Swapping up past tick 600 needs the rest of word 0
+        assert!(matches!(
+            pool.simulate_swap(pool.token_b, U256::exp10(30)),
+            Err(SwapSimulationError::TickDataOutOfRange {
+                word_position: 0,
+                ..
+            })
+        ));
+
+        let mut pool = pool;
+        pool.tick_window = None;
+        assert!(matches!(
+            pool.simulate_swap(pool.token_a, U256::from(1000000)),
+            Err(SwapSimulationError::TickDataOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_simulate_swap_clamps_to_min_and_max_tick() {
+        //The tick data request ends the window at the min/max tick, so swaps can walk up to the price limit
+        let pool = pool_with_tick_window(-887160, MIN_TICK, -886800);
+        assert!(pool.simulate_swap(pool.token_a, U256::exp10(30)).is_ok());
+
+        let pool = pool_with_tick_window(887160, 886800, MAX_TICK);
+        assert!(pool.simulate_swap(pool.token_b, U256::exp10(30)).is_ok());
+
+        //Without the min/max tick in the window, the same swaps need tick data past the window
+        let pool = pool_with_tick_window(-887160, -887160, -886800);
+        assert!(pool.simulate_swap(pool.token_a, U256::exp10(30)).is_err());
+
+        let pool = pool_with_tick_window(887160, 886800, 887160);
+        assert!(pool.simulate_swap(pool.token_b, U256::exp10(30)).is_err());
+    }
+
+    #[test]
+    fn test_simulate_swap_mut_crosses_initialized_tick() {
+        let liquidity_net = 500000000000000000;
+        let mut pool = pool_with_tick_window(0, -15360, 15300);
+        pool.ticks.insert(
+            120,
+            TickInfo {
+                liquidity_net,
+                initialized: true,
+            },
+        );
+
+        let amount_in = U256::from(10000000000000000_u128);
+        let amount_out = pool.simulate_swap(pool.token_b, amount_in).unwrap();
+
+        assert_eq!(
+            pool.simulate_swap_mut(pool.token_b, amount_in).unwrap(),
+            amount_out
+        );
+        assert!(pool.tick > 120);
+        assert_eq!(pool.liquidity, 1500000000000000000);
+        assert_eq!(pool.liquidity_net, liquidity_net);
+
+        //The window is still valid, so the pool can be swapped back locally
+        assert!(pool.simulate_swap(pool.token_a, amount_out).unwrap() < amount_in);
+    }
 }
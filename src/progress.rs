@@ -0,0 +1,257 @@
+//Progress bar rendering for the sync and checkpoint entry points.
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::errors::ProgressConfigError;
+
+pub const DEFAULT_TEMPLATE: &str = "{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}";
+pub const DEFAULT_PROGRESS_CHARS: &str = "##-";
+//Suffixes appended to the template by `start_stage_with_suffix`
+pub const BLOCKS_SUFFIX: &str = " Blocks";
+pub const PAIRS_SUFFIX: &str = " Pairs";
+pub const STAGE_SUFFIXES: [&str; 2] = [BLOCKS_SUFFIX, PAIRS_SUFFIX];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressMode {
+    //One bar per task, with a message for each stage
+    #[default]
+    Full,
+    //A single bar that aggregates the progress of all tasks
+    Minimal,
+    //No progress bars are rendered
+    Quiet,
+}
+
+//The template and progress chars are validated in `ProgressConfig::new` so that rendering the bars can not fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressConfig {
+    pub mode: ProgressMode,
+    //indicatif template used for each bar, see `ProgressStyle::with_template`
+    template: String,
+    //Fill, current and empty chars of the bar, all of the same width
+    progress_chars: String,
+}
+
+impl ProgressConfig {
+    pub fn new(
+        mode: ProgressMode,
+        template: &str,
+        progress_chars: &str,
+    ) -> Result<Self, ProgressConfigError> {
+        ProgressStyle::with_template(template)?;
+        for suffix in STAGE_SUFFIXES {
+            ProgressStyle::with_template(&format!("{}{}", template, suffix))?;
+        }
+
+        if progress_chars.chars().count() < 2 {
+            return Err(ProgressConfigError::NotEnoughProgressChars);
+        }
+
+        Ok(ProgressConfig {
+            mode,
+            template: template.to_string(),
+            progress_chars: progress_chars.to_string(),
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn progress_chars(&self) -> &str {
+        &self.progress_chars
+    }
+}
+
+impl Default for ProgressConfig {
+    fn default() -> Self {
+        ProgressConfig {
+            mode: ProgressMode::Full,
+            template: DEFAULT_TEMPLATE.to_string(),
+            progress_chars: DEFAULT_PROGRESS_CHARS.to_string(),
+        }
+    }
+}
+
+impl From<ProgressMode> for ProgressConfig {
+    fn from(mode: ProgressMode) -> Self {
+        ProgressConfig {
+            mode,
+            ..Default::default()
+        }
+    }
+}
+
+//Hands out progress bars for each task according to the progress mode.
+//Bar lengths are only ever increased via `inc_length` so that tasks can share the aggregate bar in minimal mode.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    config: ProgressConfig,
+    multi_progress_bar: Option<MultiProgress>,
+    aggregate_progress_bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    pub fn new(config: ProgressConfig) -> Self {
+        match config.mode {
+            ProgressMode::Full => Progress {
+                multi_progress_bar: Some(MultiProgress::new()),
+                aggregate_progress_bar: None,
+                config,
+            },
+
+            ProgressMode::Minimal => {
+                let progress_bar = ProgressBar::new(0);
+                progress_bar.set_style(style(&config, &config.template));
+                progress_bar.set_message("Syncing pools");
+
+                Progress {
+                    multi_progress_bar: None,
+                    aggregate_progress_bar: Some(progress_bar),
+                    config,
+                }
+            }
+
+            ProgressMode::Quiet => Progress {
+                multi_progress_bar: None,
+                aggregate_progress_bar: None,
+                config,
+            },
+        }
+    }
+
+    pub fn mode(&self) -> ProgressMode {
+        self.config.mode
+    }
+
+    //Returns a new bar in full mode, the aggregate bar in minimal mode and a hidden bar in quiet mode
+    pub fn add_bar(&self) -> ProgressBar {
+        if let Some(multi_progress_bar) = &self.multi_progress_bar {
+            multi_progress_bar.add(ProgressBar::new(0))
+        } else if let Some(aggregate_progress_bar) = &self.aggregate_progress_bar {
+            aggregate_progress_bar.clone()
+        } else {
+            ProgressBar::hidden()
+        }
+    }
+
+    //Resets the bar and sets the message for the next stage of a task. Only applies in full mode.
+    pub fn start_stage(&self, progress_bar: &ProgressBar, message: String) {
+        self.start_stage_with_suffix(progress_bar, message, "");
+    }
+
+    //Same as `start_stage`, appending `suffix`, one of `STAGE_SUFFIXES`, to the template
+    pub fn start_stage_with_suffix(
+        &self,
+        progress_bar: &ProgressBar,
+        message: String,
+        suffix: &str,
+    ) {
+        if self.config.mode == ProgressMode::Full {
+            progress_bar.reset();
+            progress_bar.set_length(0);
+            progress_bar.set_style(style(
+                &self.config,
+                &format!("{}{}", self.config.template, suffix),
+            ));
+            progress_bar.set_message(message);
+        }
+    }
+
+    //Marks the task as finished. Only applies in full mode, the aggregate bar is finished with `finish`.
+    pub fn finish_stage(&self, progress_bar: &ProgressBar, message: String) {
+        if self.config.mode == ProgressMode::Full {
+            progress_bar.finish_and_clear();
+            progress_bar.set_message(message);
+            progress_bar.finish();
+        }
+    }
+
+    //Finishes the aggregate bar once all tasks are done
+    pub fn finish(&self) {
+        if let Some(aggregate_progress_bar) = &self.aggregate_progress_bar {
+            aggregate_progress_bar.finish();
+        }
+    }
+
+    //Prints a line above the bars, unless in quiet mode
+    pub fn println(&self, message: String) {
+        match self.config.mode {
+            ProgressMode::Full | ProgressMode::Minimal => println!("{}", message),
+            ProgressMode::Quiet => {}
+        }
+    }
+}
+
+//`template` is the config template, optionally followed by one of the stage suffixes, which are validated together in `ProgressConfig::new`
+fn style(config: &ProgressConfig, template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("Progress template was validated in ProgressConfig::new")
+        .progress_chars(&config.progress_chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Progress, ProgressConfig, ProgressMode, DEFAULT_PROGRESS_CHARS};
+    use crate::errors::ProgressConfigError;
+
+    #[test]
+    fn test_minimal_mode_aggregates_lengths() {
+        let progress = Progress::new(ProgressMode::Minimal.into());
+
+        let progress_bar_a = progress.add_bar();
+        let progress_bar_b = progress.add_bar();
+
+        progress.start_stage(&progress_bar_a, "a".to_string());
+        progress_bar_a.inc_length(10);
+        progress_bar_a.inc(4);
+
+        progress.start_stage(&progress_bar_b, "b".to_string());
+        progress_bar_b.inc_length(5);
+        progress_bar_b.inc(5);
+
+        assert_eq!(progress_bar_a.length(), Some(15));
+        assert_eq!(progress_bar_b.position(), 9);
+    }
+
+    #[test]
+    fn test_full_mode_resets_stage() {
+        let progress = Progress::new(ProgressConfig::default());
+        let progress_bar = progress.add_bar();
+
+        progress_bar.inc_length(10);
+        progress_bar.inc(10);
+
+        progress.start_stage(&progress_bar, "next stage".to_string());
+        assert_eq!(progress_bar.length(), Some(0));
+        assert_eq!(progress_bar.position(), 0);
+    }
+
+    #[test]
+    fn test_quiet_mode_is_hidden() {
+        let progress = Progress::new(ProgressMode::Quiet.into());
+
+        assert!(progress.add_bar().is_hidden());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config =
+            ProgressConfig::new(ProgressMode::Minimal, "{msg} {pos}", DEFAULT_PROGRESS_CHARS)
+                .unwrap();
+        assert_eq!(config.template(), "{msg} {pos}");
+
+        assert!(matches!(
+            ProgressConfig::new(ProgressMode::Full, "{bar:40x}", DEFAULT_PROGRESS_CHARS),
+            Err(ProgressConfigError::InvalidTemplate(_))
+        ));
+        //Only invalid once a stage suffix is appended
+        assert!(matches!(
+            ProgressConfig::new(ProgressMode::Full, "{msg} }", DEFAULT_PROGRESS_CHARS),
+            Err(ProgressConfigError::InvalidTemplate(_))
+        ));
+        assert!(matches!(
+            ProgressConfig::new(ProgressMode::Full, "{msg}", "#"),
+            Err(ProgressConfigError::NotEnoughProgressChars)
+        ));
+    }
+}
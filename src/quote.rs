@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{H160, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{CFMMError, StaleQuote, SwapSimulationError},
+    health::HealthFilter,
+    pool::Pool,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quote {
+    pub token_in: H160,
+    pub token_out: H160,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    //Addresses of the pools in the route, in swap order
+    pub route: Vec<H160>,
+    //Oldest block that any pool in the route was synced at
+    pub state_block: u64,
+}
+
+impl Quote {
+    //Returns an error if the quote state is more than `max_age_blocks` behind `current_block`
+    pub fn assert_fresh(&self, current_block: u64, max_age_blocks: u64) -> Result<(), StaleQuote> {
+        if current_block.saturating_sub(self.state_block) > max_age_blocks {
+            Err(StaleQuote {
+                state_block: self.state_block,
+                current_block,
+                max_age_blocks,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+//Simulates the route and returns a quote with the block that the route state was synced at
+pub async fn quote_route<M: Middleware>(
+    token_in: H160,
+    amount_in: U256,
+    route: &[Pool],
+    middleware: Arc<M>,
+) -> Result<Quote, CFMMError<M>> {
+    let mut token_out = token_in;
+    let mut amount_out = amount_in;
+
+    for pool in route {
+        let next_token = other_token(pool, token_out)?;
+
+        amount_out = pool
+            .simulate_swap(token_out, amount_out, middleware.clone())
+            .await?;
+
+        token_out = next_token;
+    }
+
+    Ok(Quote {
+        token_in,
+        token_out,
+        amount_in,
+        amount_out,
+        route: route.iter().map(|pool| pool.address()).collect(),
+        state_block: route
+            .iter()
+            .map(|pool| pool.last_synced_block())
+            .min()
+            .unwrap_or(0),
+    })
+}
+
+//Returns the single hop quote with the largest amount out across all pools that contain token_in and token_out.
+pub async fn get_best_quote<M: Middleware>(
+    token_in: H160,
+    token_out: H160,
+    amount_in: U256,
+    pools: &[Pool],
+    middleware: Arc<M>,
+) -> Result<Option<Quote>, CFMMError<M>> {
+    get_best_quote_with_health(token_in, token_out, amount_in, pools, None, middleware).await
+}
+
+//Returns the single hop quote with the largest amount out across all pools that contain token_in and token_out.
+//If a `health_filter` is provided, pools that are not healthy according to the filter are not quoted.
+pub async fn get_best_quote_with_health<M: Middleware>(
+    token_in: H160,
+    token_out: H160,
+    amount_in: U256,
+    pools: &[Pool],
+    health_filter: Option<&HealthFilter>,
+    middleware: Arc<M>,
+) -> Result<Option<Quote>, CFMMError<M>> {
+    let mut best_quote: Option<Quote> = None;
+
+    for pool in pools {
+        let (token_a, token_b) = pool_tokens(pool);
+        if !((token_a == token_in && token_b == token_out)
+            || (token_a == token_out && token_b == token_in))
+        {
+            continue;
+        }
+
+        if let Some(health_filter) = health_filter {
+            if !health_filter.is_healthy(pool) {
+                continue;
+            }
+        }
+
+        let quote = quote_route(
+            token_in,
+            amount_in,
+            std::slice::from_ref(pool),
+            middleware.clone(),
+        )
+        .await?;
+
+        if best_quote
+            .as_ref()
+            .map(|best_quote| quote.amount_out > best_quote.amount_out)
+            .unwrap_or(true)
+        {
+            best_quote = Some(quote);
+        }
+    }
+
+    Ok(best_quote)
+}
+
+fn pool_tokens(pool: &Pool) -> (H160, H160) {
+    match pool {
+        Pool::UniswapV2(pool) => (pool.token_a, pool.token_b),
+        Pool::UniswapV3(pool) => (pool.token_a, pool.token_b),
+    }
+}
+
+//Returns the token on the other side of the pool, or an error if `token` is not in the pool
+fn other_token(pool: &Pool, token: H160) -> Result<H160, SwapSimulationError> {
+    let (token_a, token_b) = pool_tokens(pool);
+
+    if token == token_a {
+        Ok(token_b)
+    } else if token == token_b {
+        Ok(token_a)
+    } else {
+        Err(SwapSimulationError::TokenNotInPool {
+            pool: pool.address(),
+            token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
+
+    use super::{get_best_quote, get_best_quote_with_health, other_token, quote_route, Quote};
+    use crate::{
+        errors::{StaleQuote, SwapSimulationError},
+        health::{HealthFilter, HealthWeights, PoolStats},
+        pool::Pool,
+        test_utils::usdc_weth_pool,
+    };
+
+    #[test]
+    fn test_assert_fresh() {
+        let quote = Quote {
+            token_in: H160::zero(),
+            token_out: H160::zero(),
+            amount_in: U256::zero(),
+            amount_out: U256::zero(),
+            route: vec![],
+            state_block: 100,
+        };
+
+        assert!(quote.assert_fresh(100, 0).is_ok());
+        assert!(quote.assert_fresh(105, 5).is_ok());
+        assert_eq!(
+            quote.assert_fresh(106, 5),
+            Err(StaleQuote {
+                state_block: 100,
+                current_block: 106,
+                max_age_blocks: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_other_token() {
+        let pool = Pool::UniswapV2(usdc_weth_pool(0));
+        let usdc = H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+        let weth = H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+
+        assert_eq!(other_token(&pool, usdc).unwrap(), weth);
+        assert_eq!(other_token(&pool, weth).unwrap(), usdc);
+        assert!(matches!(
+            other_token(&pool, H160::from_low_u64_be(1)),
+            Err(SwapSimulationError::TokenNotInPool { token, .. }) if token == H160::from_low_u64_be(1)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quote_route_state_block() {
+        //V2 simulation does not make any calls so the provider is never used
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let route = [
+            Pool::UniswapV2(usdc_weth_pool(120)),
+            Pool::UniswapV2(usdc_weth_pool(110)),
+        ];
+        let usdc = H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+
+        let quote = quote_route(usdc, U256::from(1000000000), &route, middleware.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(quote.state_block, 110);
+        assert_eq!(quote.token_out, usdc);
+        assert_eq!(quote.route.len(), 2);
+
+        let best_quote = get_best_quote(
+            usdc,
+            H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+            U256::from(1000000000),
+            &route,
+            middleware,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(best_quote.route.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_best_quote_with_health() {
+        let middleware = Arc::new(Provider::<Http>::try_from("http://localhost:8545").unwrap());
+
+        let usdc = H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap();
+        let weth = H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap();
+
+        let mut stale_pool = usdc_weth_pool(0);
+        stale_pool.address = H160::from_low_u64_be(1);
+        //The stale pool would give the better quote if it was not excluded
+        stale_pool.reserve_1 *= 2;
+        let stale_pool = Pool::UniswapV2(stale_pool);
+        let pools = [Pool::UniswapV2(usdc_weth_pool(1000)), stale_pool.clone()];
+
+        let stats = pools
+            .iter()
+            .map(|pool| {
+                (
+                    pool.address(),
+                    PoolStats {
+                        quote_token: weth,
+                        current_block: 1000,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        let health_filter = HealthFilter::new(stats, HealthWeights::default(), 0.8);
+
+        let best_quote = get_best_quote(
+            usdc,
+            weth,
+            U256::from(1000000000),
+            &pools,
+            middleware.clone(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(best_quote.route, vec![stale_pool.address()]);
+
+        let best_quote = get_best_quote_with_health(
+            usdc,
+            weth,
+            U256::from(1000000000),
+            &pools,
+            Some(&health_filter),
+            middleware,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(best_quote.route, vec![pools[0].address()]);
+    }
+}
@@ -0,0 +1,380 @@
+//Retries RPC calls that fail because the provider is rate limiting requests.
+//Clones of a `RetryPolicy` share the same backoff, so a rate limited call in one task also delays the calls made by other tasks.
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ethers::{
+    contract::MulticallError,
+    prelude::ContractError,
+    providers::{JsonRpcError, Middleware, MiddlewareError, ProviderError, RpcError},
+};
+use futures::future::BoxFuture;
+use rand::Rng;
+
+use crate::errors::{CFMMError, RateLimitedError};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+//JSON-RPC error code used by Infura and others when the request rate is exceeded
+const LIMIT_EXCEEDED_ERROR_CODE: i64 = -32005;
+const TOO_MANY_REQUESTS_ERROR_CODE: i64 = 429;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    //Calls are not made until this instant has passed
+    backoff_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(DEFAULT_MAX_RETRIES, DEFAULT_BASE_DELAY, DEFAULT_MAX_DELAY)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32, base_delay: Duration, max_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay,
+            max_delay,
+            backoff_until: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    //Rate limited calls fail immediately with `CFMMError::RateLimitExceeded`
+    pub fn disabled() -> RetryPolicy {
+        RetryPolicy::new(0, Duration::ZERO, Duration::ZERO)
+    }
+
+    //Delay before the given retry, doubling from `base_delay` for each retry up to `max_delay`
+    pub fn backoff_delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .checked_mul(2_u32.saturating_pow(retry))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    //Backoff delay with jitter, uniformly distributed between half and all of the backoff delay
+    pub fn jittered_backoff_delay(&self, retry: u32) -> Duration {
+        let delay = self.backoff_delay(retry);
+        let jitter = rand::thread_rng().gen_range(0.0..=1.0);
+
+        delay / 2 + (delay / 2).mul_f64(jitter)
+    }
+
+    //Calls `f` until it succeeds, returns an error that is not due to rate limiting or `max_retries` is exceeded
+    pub async fn retry<T, M, F, Fut>(&self, mut f: F) -> Result<T, CFMMError<M>>
+    where
+        M: Middleware,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CFMMError<M>>>,
+    {
+        let mut retries = 0;
+
+        loop {
+            self.wait_for_backoff().await;
+
+            match f().await {
+                Err(err) => retries = self.backoff(err, retries)?,
+                result => return result,
+            }
+        }
+    }
+
+    //Same as `retry`, for calls that need mutable access to `state`, eg. batch requests that populate a slice of pools
+    pub async fn retry_with<S, T, M, F>(&self, state: &mut S, mut f: F) -> Result<T, CFMMError<M>>
+    where
+        S: ?Sized,
+        M: Middleware,
+        F: for<'a> FnMut(&'a mut S) -> BoxFuture<'a, Result<T, CFMMError<M>>>,
+    {
+        let mut retries = 0;
+
+        loop {
+            self.wait_for_backoff().await;
+
+            match f(state).await {
+                Err(err) => retries = self.backoff(err, retries)?,
+                result => return result,
+            }
+        }
+    }
+
+    //Returns the error if it should not be retried, otherwise extends the shared backoff and returns the incremented retry count
+    fn backoff<M: Middleware>(&self, err: CFMMError<M>, retries: u32) -> Result<u32, CFMMError<M>> {
+        if !is_rate_limit_error(&err) {
+            return Err(err);
+        }
+
+        if retries >= self.max_retries {
+            return Err(CFMMError::RateLimitExceeded {
+                retries,
+                error: RateLimitedError(Box::new(err)),
+            });
+        }
+
+        let backoff_until = Instant::now() + self.jittered_backoff_delay(retries);
+        let mut current_backoff_until = self
+            .backoff_until
+            .lock()
+            .expect("Error when acquiring retry policy mutex lock");
+
+        //Only ever extend the backoff, `None` orders before any instant
+        *current_backoff_until = (*current_backoff_until).max(Some(backoff_until));
+
+        Ok(retries + 1)
+    }
+
+    async fn wait_for_backoff(&self) {
+        let backoff_until = *self
+            .backoff_until
+            .lock()
+            .expect("Error when acquiring retry policy mutex lock");
+
+        if let Some(backoff_until) = backoff_until {
+            tokio::time::sleep_until(backoff_until.into()).await;
+        }
+    }
+}
+
+//Returns true if the error was caused by the provider rate limiting requests
+pub fn is_rate_limit_error<M: Middleware>(err: &CFMMError<M>) -> bool {
+    match err {
+        CFMMError::MiddlewareError(err) => is_rate_limit_response(err.as_error_response(), err),
+        CFMMError::ProviderError(err) => is_provider_rate_limit_error(err),
+        CFMMError::ContractError(ContractError::MiddlewareError { e }) => {
+            is_rate_limit_response(e.as_error_response(), e)
+        }
+        CFMMError::ContractError(ContractError::ProviderError { e })
+        | CFMMError::MulticallError(MulticallError::ContractError(
+            ContractError::ProviderError { e },
+        )) => is_provider_rate_limit_error(e),
+        CFMMError::MulticallError(MulticallError::ContractError(
+            ContractError::MiddlewareError { e },
+        )) => is_rate_limit_response(e.as_error_response(), e),
+        _ => false,
+    }
+}
+
+//Returns true if the error came from the middleware rather than from the contract that was called
+pub fn is_middleware_error<M: Middleware>(err: &CFMMError<M>) -> bool {
+    matches!(
+        err,
+        CFMMError::MiddlewareError(_)
+            | CFMMError::RateLimitExceeded { .. }
+            | CFMMError::ProviderError(_)
+            | CFMMError::ContractError(ContractError::MiddlewareError { .. })
+            | CFMMError::ContractError(ContractError::ProviderError { .. })
+            | CFMMError::MulticallError(MulticallError::ContractError(
+                ContractError::MiddlewareError { .. } | ContractError::ProviderError { .. }
+            ))
+    )
+}
+
+fn is_provider_rate_limit_error(err: &ProviderError) -> bool {
+    is_rate_limit_response(RpcError::as_error_response(err), err)
+}
+
+fn is_rate_limit_response(
+    error_response: Option<&JsonRpcError>,
+    err: &impl std::error::Error,
+) -> bool {
+    //JSON-RPC errors are classified by their code alone, so reverts that mention a rate limit are not retried
+    if let Some(error_response) = error_response {
+        return error_response.code == TOO_MANY_REQUESTS_ERROR_CODE
+            || error_response.code == LIMIT_EXCEEDED_ERROR_CODE;
+    }
+
+    //HTTP 429 responses and other errors without a JSON-RPC response only signal rate limiting through the error message
+    let message = err.to_string().to_lowercase();
+
+    message.contains("too many requests")
+        || message.contains("rate limit")
+        || message.contains("compute units")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use ethers::{
+        providers::{JsonRpcError, MockError, ProviderError},
+        types::H160,
+    };
+    use futures::FutureExt;
+
+    use super::{is_rate_limit_error, RetryPolicy};
+    use crate::{errors::CFMMError, mock_middleware::MockMiddleware, pool::UniswapV2Pool};
+
+    fn rate_limit_error(code: i64, message: &str) -> ProviderError {
+        ProviderError::JsonRpcClientError(Box::new(MockError::JsonRpcError(JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        })))
+    }
+
+    fn pool() -> UniswapV2Pool {
+        UniswapV2Pool {
+            address: H160::from_low_u64_be(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let retry_policy =
+            RetryPolicy::new(10, Duration::from_millis(100), Duration::from_millis(1000));
+
+        assert_eq!(retry_policy.backoff_delay(0), Duration::from_millis(100));
+        assert_eq!(retry_policy.backoff_delay(1), Duration::from_millis(200));
+        assert_eq!(retry_policy.backoff_delay(3), Duration::from_millis(800));
+        assert_eq!(retry_policy.backoff_delay(4), Duration::from_millis(1000));
+        assert_eq!(retry_policy.backoff_delay(64), Duration::from_millis(1000));
+
+        for retry in 0..5 {
+            let delay = retry_policy.jittered_backoff_delay(retry);
+            assert!(delay >= retry_policy.backoff_delay(retry) / 2);
+            assert!(delay <= retry_policy.backoff_delay(retry));
+        }
+    }
+
+    #[test]
+    fn test_is_rate_limit_error() {
+        let is_rate_limit_error = |err: ProviderError| {
+            is_rate_limit_error::<MockMiddleware>(&CFMMError::MiddlewareError(err))
+        };
+
+        assert!(is_rate_limit_error(rate_limit_error(429, "")));
+        assert!(is_rate_limit_error(rate_limit_error(
+            -32005,
+            "limit exceeded"
+        )));
+        assert!(is_rate_limit_error(rate_limit_error(
+            429,
+            "Your app has exceeded its compute units per second capacity"
+        )));
+        assert!(is_rate_limit_error(ProviderError::CustomError(
+            "Your app has exceeded its compute units per second capacity".to_string()
+        )));
+        assert!(is_rate_limit_error(ProviderError::CustomError(
+            "429 Too Many Requests".to_string()
+        )));
+        assert!(!is_rate_limit_error(rate_limit_error(
+            3,
+            "execution reverted"
+        )));
+        assert!(!is_rate_limit_error(rate_limit_error(
+            3,
+            "execution reverted: rate limit exceeded"
+        )));
+        assert!(!is_rate_limit_error(ProviderError::CustomError(
+            "connection closed".to_string()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let middleware =
+            Arc::new(MockMiddleware::new(100).with_reserves(H160::from_low_u64_be(1), 10, 20));
+        for _ in 0..3 {
+            middleware.push_error(rate_limit_error(429, "Too Many Requests"));
+        }
+
+        let retry_policy =
+            RetryPolicy::new(5, Duration::from_millis(20), Duration::from_millis(50));
+        let mut pool = pool();
+
+        let start = Instant::now();
+        retry_policy
+            .retry_with(&mut pool, |pool| pool.sync_pool(middleware.clone()).boxed())
+            .await
+            .unwrap();
+
+        //Three rate limited requests followed by the reserves request
+        assert_eq!(middleware.requests(), 4);
+        assert_eq!((pool.reserve_0, pool.reserve_1), (10, 20));
+
+        //Jittered delays are at least half of the 20ms, 40ms and 50ms (capped) backoff delays
+        assert!(start.elapsed() >= Duration::from_millis(55));
+    }
+
+    #[tokio::test]
+    async fn test_retry_exceeds_max_retries() {
+        let middleware = Arc::new(MockMiddleware::new(100));
+        for _ in 0..4 {
+            middleware.push_error(rate_limit_error(-32005, "limit exceeded"));
+        }
+
+        let retry_policy = RetryPolicy::new(2, Duration::from_millis(1), Duration::from_millis(1));
+
+        let result = retry_policy
+            .retry(|| {
+                let middleware = middleware.clone();
+                async move { pool().sync_pool(middleware).await }
+            })
+            .await;
+
+        assert_eq!(middleware.requests(), 3);
+
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Rate limit exceeded after 2 retries: Contract error"
+        );
+        //The source is the error returned by the last rate limited call
+        assert!(std::error::Error::source(&err)
+            .is_some_and(|source| source.to_string() == "Contract error"));
+        assert!(matches!(
+            err,
+            CFMMError::RateLimitExceeded { retries: 2, error } if matches!(*error.0, CFMMError::ContractError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error_is_not_retried() {
+        let middleware = Arc::new(MockMiddleware::new(100));
+        middleware.push_error(ProviderError::CustomError("connection closed".to_string()));
+
+        let result = RetryPolicy::default()
+            .retry(|| {
+                let middleware = middleware.clone();
+                async move { pool().sync_pool(middleware).await }
+            })
+            .await;
+
+        assert_eq!(middleware.requests(), 1);
+        assert!(matches!(result, Err(CFMMError::ContractError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_backoff_is_shared_between_clones() {
+        let retry_policy =
+            RetryPolicy::new(1, Duration::from_millis(100), Duration::from_millis(100));
+        let other_retry_policy = retry_policy.clone();
+
+        //Back off on one clone, then check that the other clone waits for the backoff
+        retry_policy
+            .backoff(
+                CFMMError::<MockMiddleware>::MiddlewareError(rate_limit_error(429, "")),
+                0,
+            )
+            .unwrap();
+
+        let start = Instant::now();
+        other_retry_policy.wait_for_backoff().await;
+
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
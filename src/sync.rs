@@ -1,10 +1,15 @@
-use crate::{checkpoint, errors::CFMMError};
+use crate::{
+    checkpoint,
+    errors::CFMMError,
+    import,
+    progress::{Progress, ProgressConfig},
+    retry::RetryPolicy,
+};
 
-use super::dex::Dex;
+use super::dex::{Dex, DexVariant};
 use super::pool::Pool;
-use super::throttle::RequestThrottle;
-use ethers::providers::Middleware;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use super::throttle::{increment_or_sleep, RequestThrottle};
+use ethers::{providers::Middleware, types::H160};
 use std::{
     panic::resume_unwind,
     sync::{Arc, Mutex},
@@ -39,37 +44,68 @@ pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
     requests_per_second_limit: usize,
     checkpoint_path: Option<&str>,
 ) -> Result<Vec<Pool>, CFMMError<M>> {
-    let current_block = middleware
-        .get_block_number()
-        .await
-        .map_err(CFMMError::MiddlewareError)?;
+    sync_pairs_with_progress(
+        dexes,
+        step,
+        middleware,
+        requests_per_second_limit,
+        checkpoint_path,
+        ProgressConfig::default(),
+        RetryPolicy::default(),
+    )
+    .await
+}
 
+//Get all pairs and sync reserve values for each Dex in the `dexes` vec, rendering progress according to `progress_config`.
+//Rate limited requests are retried according to `retry_policy`, which is shared by the tasks syncing each Dex.
+pub async fn sync_pairs_with_progress<M: 'static + Middleware>(
+    dexes: Vec<Dex>,
+    step: usize,
+    middleware: Arc<M>,
+    requests_per_second_limit: usize,
+    checkpoint_path: Option<&str>,
+    progress_config: ProgressConfig,
+    retry_policy: RetryPolicy,
+) -> Result<Vec<Pool>, CFMMError<M>> {
     //Initialize a new request throttle
-    let request_throttle = Arc::new(Mutex::new(RequestThrottle::new(requests_per_second_limit)));
+    let request_throttle = Arc::new(Mutex::new(
+        RequestThrottle::new(requests_per_second_limit).with_retry_policy(retry_policy.clone()),
+    ));
+
+    let current_block = retry_policy
+        .retry(|| {
+            let (middleware, request_throttle) = (middleware.clone(), &request_throttle);
+            async move {
+                increment_or_sleep(request_throttle, 1).await;
+                middleware
+                    .get_block_number()
+                    .await
+                    .map_err(CFMMError::MiddlewareError)
+            }
+        })
+        .await?;
 
     //Aggregate the populated pools from each thread
     let mut aggregated_pools: Vec<Pool> = vec![];
     let mut handles = vec![];
 
-    //Initialize multi progress bar
-    let multi_progress_bar = MultiProgress::new();
+    //Initialize the progress bars
+    let progress = Progress::new(progress_config);
 
     //For each dex supplied, get all pair created events and get reserve values
     for dex in dexes.clone() {
         let middleware = middleware.clone();
         let request_throttle = request_throttle.clone();
-        let progress_bar = multi_progress_bar.add(ProgressBar::new(0));
+        let progress = progress.clone();
+        let progress_bar = progress.add_bar();
 
         //Spawn a new thread to get all pools and sync data for each dex
         handles.push(tokio::spawn(async move {
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pools from the dex
-            progress_bar.set_message(format!("Getting all pools from: {}", dex.factory_address()));
+            progress.start_stage(
+                &progress_bar,
+                format!("Getting all pools from: {}", dex.factory_address()),
+            );
 
             let mut pools = dex
                 .get_all_pools(
@@ -80,22 +116,17 @@ pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
                 )
                 .await?;
 
-            progress_bar.reset();
-            progress_bar.set_style(
-                ProgressStyle::with_template("{msg} {bar:40.cyan/blue} {pos:>7}/{len:7}")
-                    .expect("Error when setting progress bar style")
-                    .progress_chars("##-"),
-            );
-
             //Get all of the pool data and sync the pool
-            progress_bar.set_message(format!(
-                "Getting all pool data for: {}",
-                dex.factory_address()
-            ));
-            progress_bar.set_length(pools.len() as u64);
+            progress.start_stage(
+                &progress_bar,
+                format!("Getting all pool data for: {}", dex.factory_address()),
+            );
+            progress_bar.inc_length(pools.len() as u64);
 
-            dex.get_all_pool_data(
+            //Populated pools are marked as synced at the current block
+            dex.get_all_pool_data_at_block(
                 &mut pools,
+                Some(current_block),
                 request_throttle.clone(),
                 progress_bar.clone(),
                 middleware.clone(),
@@ -123,27 +154,47 @@ pub async fn sync_pairs_with_throttle<M: 'static + Middleware>(
         }
     }
 
-    //Save a checkpoint if a path is provided
-    if checkpoint_path.is_some() {
-        let checkpoint_path = checkpoint_path.unwrap();
+    progress.finish();
 
+    //Save a checkpoint if a path is provided
+    if let Some(checkpoint_path) = checkpoint_path {
         checkpoint::construct_checkpoint(
             dexes,
             &aggregated_pools,
             current_block.as_u64(),
             checkpoint_path,
-        )
+        )?;
     }
 
     //Return the populated aggregated pools vec
     Ok(aggregated_pools)
 }
 
+//Creates a pool for each address and populates the pool data via batched calls.
+//Pools that could not be populated are removed.
+pub async fn sync_pools_from_addresses<M: 'static + Middleware>(
+    addresses: Vec<(H160, DexVariant)>,
+    middleware: Arc<M>,
+) -> Result<Vec<Pool>, CFMMError<M>> {
+    let current_block = middleware
+        .get_block_number()
+        .await
+        .map_err(CFMMError::MiddlewareError)?;
+
+    let pools = addresses
+        .into_iter()
+        .map(|(address, dex_variant)| Pool::new_empty(address, dex_variant))
+        .collect();
+
+    //Populated pools are marked as synced at the current block
+    import::populate_pools(pools, Some(current_block), middleware).await
+}
+
 pub fn remove_empty_pools(pools: Vec<Pool>) -> Vec<Pool> {
     let mut cleaned_pools = vec![];
 
     for pool in pools {
-        match pool {
+        match &pool {
             Pool::UniswapV2(uniswap_v2_pool) => {
                 if !uniswap_v2_pool.token_a.is_zero() {
                     cleaned_pools.push(pool)
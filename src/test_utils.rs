@@ -0,0 +1,21 @@
+//Fixtures shared by the unit tests
+use std::str::FromStr;
+
+use ethers::types::H160;
+
+use crate::pool::UniswapV2Pool;
+
+//The Uniswap V2 USDC/WETH pair with fixed reserves
+pub fn usdc_weth_pool(last_synced_block: u64) -> UniswapV2Pool {
+    UniswapV2Pool {
+        address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc").unwrap(),
+        token_a: H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48").unwrap(),
+        token_a_decimals: 6,
+        token_b: H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2").unwrap(),
+        token_b_decimals: 18,
+        reserve_0: 47092140895915,
+        reserve_1: 28396598565590008529300,
+        fee: 300,
+        last_synced_block,
+    }
+}
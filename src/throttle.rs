@@ -1,49 +1,131 @@
 use std::{
-    thread::sleep,
-    time::{Duration, SystemTime},
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
+use crate::retry::RetryPolicy;
+
+const THROTTLE_WINDOW: Duration = Duration::from_secs(1);
+
 pub struct RequestThrottle {
     enabled: bool,
-    last_request_timestamp: SystemTime,
+    //Start of the current one second window, this can be in the future when requests have been scheduled ahead
+    window_start: Instant,
     requests_per_second_limit: usize,
     requests_per_second: usize,
+    //Shared by every task that uses the throttle so that backing off in one task also slows the others
+    retry_policy: RetryPolicy,
 }
 
 impl RequestThrottle {
     pub fn new(requests_per_second_limit: usize) -> RequestThrottle {
-        if requests_per_second_limit > 0 {
-            RequestThrottle {
-                enabled: true,
-                last_request_timestamp: SystemTime::now(),
-                requests_per_second_limit,
-                requests_per_second: 0,
-            }
-        } else {
-            RequestThrottle {
-                enabled: false,
-                last_request_timestamp: SystemTime::now(),
-                requests_per_second_limit,
-                requests_per_second: 0,
-            }
+        RequestThrottle {
+            enabled: requests_per_second_limit > 0,
+            window_start: Instant::now(),
+            requests_per_second_limit,
+            requests_per_second: 0,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> RequestThrottle {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy.clone()
+    }
+
+    //Counts `inc` requests against the limit and returns how long the caller should wait before sending them.
+    //This does not sleep, so the throttle can be shared behind a lock without blocking the other tasks that use it.
+    pub fn increment(&mut self, inc: usize) -> Duration {
+        if !self.enabled {
+            return Duration::ZERO;
         }
+
+        let now = Instant::now();
+        if now.saturating_duration_since(self.window_start) >= THROTTLE_WINDOW {
+            self.window_start = now;
+            self.requests_per_second = 0;
+        }
+
+        //Once the current window is full, schedule the requests in the next window
+        if self.requests_per_second >= self.requests_per_second_limit {
+            self.window_start += THROTTLE_WINDOW;
+            self.requests_per_second = 0;
+        }
+
+        self.requests_per_second += inc;
+        self.window_start.saturating_duration_since(now)
     }
 
+    //Counts `inc` requests against the limit and blocks the current thread until they can be sent.
+    //Blocking stalls the other tasks on the same runtime thread, so async code should use `throttle::increment_or_sleep`.
+    #[deprecated(
+        note = "blocks the thread, use `increment` or `throttle::increment_or_sleep` instead"
+    )]
     pub fn increment_or_sleep(&mut self, inc: usize) {
-        let time_elapsed = self
-            .last_request_timestamp
-            .elapsed()
-            .expect("Could not get time elapsed from last request timestamp")
-            .as_millis();
-
-        if self.enabled && time_elapsed < 1000 {
-            if self.requests_per_second >= self.requests_per_second_limit {
-                sleep(Duration::from_secs(1));
-                self.requests_per_second = 0;
-                self.last_request_timestamp = SystemTime::now();
-            } else {
-                self.requests_per_second += inc;
-            }
+        let delay = self.increment(inc);
+
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+}
+
+//Counts `inc` requests against the shared throttle, then waits until they can be sent.
+//The lock is released before waiting so that other tasks can schedule their requests in the meantime.
+pub async fn increment_or_sleep(request_throttle: &Mutex<RequestThrottle>, inc: usize) {
+    let delay = request_throttle
+        .lock()
+        .expect("Error when acquiring request throttle mutex lock")
+        .increment(inc);
+
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{RequestThrottle, THROTTLE_WINDOW};
+
+    #[test]
+    fn test_increment_schedules_requests_over_the_limit_in_later_windows() {
+        let mut request_throttle = RequestThrottle::new(2);
+
+        assert_eq!(request_throttle.increment(1), Duration::ZERO);
+        assert_eq!(request_throttle.increment(1), Duration::ZERO);
+
+        //Each window only allows two requests, so the next requests wait for the following windows
+        let delay = request_throttle.increment(1);
+        assert!(delay > Duration::ZERO && delay <= THROTTLE_WINDOW);
+        assert!(request_throttle.increment(1) <= THROTTLE_WINDOW);
+
+        let delay = request_throttle.increment(1);
+        assert!(delay > THROTTLE_WINDOW && delay <= THROTTLE_WINDOW * 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_increment_or_sleep_counts_requests() {
+        let mut request_throttle = RequestThrottle::new(1);
+
+        request_throttle.increment_or_sleep(1);
+
+        //The request was counted, so the window is full
+        assert!(request_throttle.increment(1) > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_increment_disabled() {
+        let mut request_throttle = RequestThrottle::new(0);
+
+        for _ in 0..100 {
+            assert_eq!(request_throttle.increment(1), Duration::ZERO);
         }
     }
 }